@@ -0,0 +1,178 @@
+use crate::color::rotate_hue;
+use crate::{raster, Composition};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame};
+use std::error::Error;
+use std::f32::consts::PI;
+
+// 1周のコマ数の範囲と省略したときの値
+pub const MIN_FRAMES: u32 = 2;
+pub const MAX_FRAMES: u32 = 60;
+pub const DEFAULT_FRAMES: u32 = 24;
+// 1周にかける時間 (ミリ秒) の範囲と省略したときの値
+pub const MIN_DURATION: u32 = 100;
+pub const MAX_DURATION: u32 = 10000;
+pub const DEFAULT_DURATION: u32 = 2000;
+// 1コマの表示時間 (ミリ秒) の下限
+// ブラウザはこれより短い GIF のコマを遅く表示するので、1周の時間が守られなくなる
+pub const MIN_FRAME_DELAY: u32 = 20;
+// アニメーションを書き出せる最大のサイズ
+pub const MAX_ANIMATION_SIZE: u32 = 512;
+// 大きさを変えるときの振れ幅 (元の大きさに対する割合)
+const PULSE_AMPLITUDE: f32 = 0.15;
+
+// 図形の動かし方
+#[derive(Clone, Copy)]
+pub enum Motion {
+    // 図形を1周回転させる
+    Rotate,
+    // 図形を膨らませたり縮めたりする
+    Pulse,
+    // 図形の色相を1周させる
+    Colors,
+}
+
+impl Motion {
+    pub fn from_name(name: &str) -> Option<Motion> {
+        match name {
+            "rotate" => Some(Motion::Rotate),
+            "pulse" => Some(Motion::Pulse),
+            "colors" => Some(Motion::Colors),
+            _ => None,
+        }
+    }
+}
+
+// アニメーションの指定
+pub struct Animation {
+    pub motion: Motion,
+    pub frames: u32,
+    // 1周にかける時間 (ミリ秒)
+    pub duration: u32,
+}
+
+impl Animation {
+    // GIF の各コマの表示時間 (1/100 秒単位)
+    // GIF の表示時間は 1/100 秒刻みなので、コマの終わる時刻を丸めて差を取り、1周の時間を保つ
+    fn gif_delays(&self) -> impl Iterator<Item = u32> {
+        let (duration, frames) = (self.duration, self.frames);
+        let end = move |i: u32| (duration * i + frames * 5) / (frames * 10);
+        (0..frames).map(move |i| end(i + 1) - end(i))
+    }
+
+    // 1周のうち t (0.0 から 1.0) の時点の構図を作る関数
+    // t = 0.0 では元の構図と同じになるので、最初のコマは静止画のアイコンと一致する
+    fn frame(&self, composition: &Composition, t: f32) -> Composition {
+        let mut frame = composition.clone();
+        for (i, shape) in frame.shapes.iter_mut().enumerate() {
+            // 隣り合う図形が逆向きに動くように交互に向きを変える
+            let direction = if i % 2 == 0 { 1.0 } else { -1.0 };
+            match self.motion {
+                Motion::Rotate => shape.angle += direction * 2.0 * PI * t,
+                Motion::Pulse => {
+                    let scale = 1.0 + direction * PULSE_AMPLITUDE * (2.0 * PI * t).sin();
+                    shape.size = (shape.size as f32 * scale).round() as u32;
+                }
+                Motion::Colors => {
+                    shape.color = rotate_hue(shape.color, 360.0 * t);
+                    if let Some(gradient) = &mut shape.gradient {
+                        gradient.rotate_hue(360.0 * t);
+                    }
+                    if let Some(pattern) = &mut shape.pattern {
+                        pattern.rotate_hue(360.0 * t);
+                    }
+                }
+            }
+        }
+        frame
+    }
+
+    // 各コマを描画する関数
+    fn render_frames<'a>(
+        &'a self,
+        composition: &'a Composition,
+        size: u32,
+        antialias: bool,
+    ) -> impl Iterator<Item = image::RgbaImage> + 'a {
+        (0..self.frames).map(move |i| {
+            let frame = self.frame(composition, i as f32 / self.frames as f32);
+            raster::draw_icon(&frame, size, antialias)
+        })
+    }
+
+    // アニメーション GIF を書き出す関数
+    pub fn encode_gif(
+        &self,
+        composition: &Composition,
+        size: u32,
+        antialias: bool,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut buffer = Vec::new();
+        {
+            // 減色は遅いので品質を少し落として速度を優先する
+            let mut encoder = GifEncoder::new_with_speed(&mut buffer, 10);
+            encoder.set_repeat(Repeat::Infinite)?;
+            let frames = self.render_frames(composition, size, antialias);
+            for (img, delay) in frames.zip(self.gif_delays()) {
+                let delay = Delay::from_numer_denom_ms(delay * 10, 1);
+                encoder.encode_frame(Frame::from_parts(img, 0, 0, delay))?;
+            }
+        }
+        Ok(buffer)
+    }
+
+    // APNG を書き出す関数
+    // APNG に対応していないビューアでは最初のコマが静止画として表示される
+    pub fn encode_apng(
+        &self,
+        composition: &Composition,
+        size: u32,
+        antialias: bool,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut buffer = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut buffer, size, size);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_animated(self.frames, 0)?;
+            // 1コマの表示時間は duration / frames ミリ秒を分数のまま書き込む
+            // duration と frames の範囲なら分子も分母も u16 に収まる
+            encoder.set_frame_delay(self.duration as u16, (self.frames * 1000) as u16)?;
+            let mut writer = encoder.write_header()?;
+            for img in self.render_frames(composition, size, antialias) {
+                writer.write_image_data(img.as_raw())?;
+            }
+            writer.finish()?;
+        }
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animation(frames: u32, duration: u32) -> Animation {
+        Animation {
+            motion: Motion::Rotate,
+            frames,
+            duration,
+        }
+    }
+
+    #[test]
+    fn gif_delays_keep_the_loop_length() {
+        for (frames, duration) in [(24, 2000), (60, 1200), (7, 1000), (2, 100), (60, 10000)] {
+            let delays: Vec<u32> = animation(frames, duration).gif_delays().collect();
+            assert_eq!(delays.len(), frames as usize);
+            assert_eq!(delays.iter().sum::<u32>(), (duration + 5) / 10);
+            assert!(delays.iter().all(|&delay| delay >= MIN_FRAME_DELAY / 10));
+        }
+    }
+
+    #[test]
+    fn gif_delays_spread_the_rounding_over_the_loop() {
+        let delays: Vec<u32> = animation(24, 2000).gif_delays().collect();
+        assert!(delays.iter().all(|&delay| delay == 8 || delay == 9));
+    }
+}
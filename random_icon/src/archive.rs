@@ -0,0 +1,16 @@
+use std::io::{Cursor, Write};
+use zip::result::ZipResult;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+// ファイル名と中身の組をまとめて ZIP にする関数
+// 更新日時は固定なので、同じ中身なら同じ ZIP になる
+pub fn zip_files(files: &[(String, Vec<u8>)]) -> ZipResult<Vec<u8>> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, data) in files {
+        writer.start_file(name.as_str(), options)?;
+        writer.write_all(data)?;
+    }
+    Ok(writer.finish()?.into_inner())
+}
@@ -0,0 +1,135 @@
+use crate::Composition;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+// 1回のリクエストで生成できるアイコンの数の上限
+pub const MAX_BATCH_ICONS: usize = 1000;
+// 1回のリクエストで描画する画素の数の合計の上限 (アニメーションでは全コマの合計)
+// 既定の大きさの静止画なら MAX_BATCH_ICONS 個まで生成できる
+const MAX_BATCH_PIXELS: u64 = 256 * 1024 * 1024;
+// 識別子の長さの上限
+const MAX_IDENTIFIER_LENGTH: usize = 256;
+// ZIP に入れる対応表のファイル名
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+// まとめて生成するアイコンの指定
+// count と identifiers のどちらか一方を指定する
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchRequest {
+    // 指定した数だけランダムな識別子でアイコンを生成する
+    count: Option<usize>,
+    // 識別子ごとに /icons/{identifier} と同じアイコンを生成する
+    identifiers: Option<Vec<String>>,
+}
+
+impl BatchRequest {
+    // アイコンを生成する識別子の一覧を返す関数
+    // count だけが指定されていれば UUID を識別子として割り当てる
+    pub fn identifiers(self) -> Result<Vec<String>, String> {
+        let identifiers = match (self.count, self.identifiers) {
+            (Some(count), None) => {
+                check_batch_size(count)?;
+                (0..count).map(|_| Uuid::new_v4().to_string()).collect()
+            }
+            (None, Some(identifiers)) => {
+                check_batch_size(identifiers.len())?;
+                identifiers
+            }
+            _ => return Err("specify either count or identifiers".to_string()),
+        };
+
+        let mut seen = HashSet::new();
+        for identifier in &identifiers {
+            if identifier.is_empty() || identifier.len() > MAX_IDENTIFIER_LENGTH {
+                return Err(format!(
+                    "identifiers must be 1 to {} bytes long: \"{}\"",
+                    MAX_IDENTIFIER_LENGTH, identifier
+                ));
+            }
+            if !seen.insert(identifier.as_str()) {
+                return Err(format!("duplicate identifier: \"{}\"", identifier));
+            }
+        }
+        Ok(identifiers)
+    }
+}
+
+// 生成する数が範囲内にあるか確かめる関数
+fn check_batch_size(count: usize) -> Result<(), String> {
+    if (1..=MAX_BATCH_ICONS).contains(&count) {
+        Ok(())
+    } else {
+        Err(format!(
+            "a batch must contain between 1 and {} icons: {}",
+            MAX_BATCH_ICONS, count
+        ))
+    }
+}
+
+// 一辺 size ピクセルで frames コマのアイコンを icons 個描画する手間が上限を超えないか確かめる関数
+pub fn check_batch_cost(icons: usize, size: u32, frames: u32) -> Result<(), String> {
+    let pixels = icons as u64 * size as u64 * size as u64 * frames as u64;
+    if pixels > MAX_BATCH_PIXELS {
+        return Err(format!(
+            "the batch is too large to render: {} icons of {}x{} pixels{} exceed {} pixels in total, use fewer icons or a smaller size",
+            icons,
+            size,
+            size,
+            if frames > 1 {
+                format!(" with {} frames", frames)
+            } else {
+                String::new()
+            },
+            MAX_BATCH_PIXELS
+        ));
+    }
+    Ok(())
+}
+
+// 識別子から ZIP の中のファイル名を作る関数
+// パスとして解釈される文字は _ に置き換え、置き換えた結果が重なれば番号を付ける
+pub fn file_name(identifier: &str, extension: &str, used: &mut HashSet<String>) -> String {
+    let stem: String = identifier
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | '@' | '+') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    // . で始まる名前は隠しファイルや親ディレクトリとして扱われるので避ける
+    let stem = if stem.starts_with('.') {
+        format!("_{}", stem)
+    } else {
+        stem
+    };
+
+    let mut name = format!("{}.{}", stem, extension);
+    let mut n = 1;
+    while !used.insert(name.clone()) {
+        n += 1;
+        name = format!("{}-{}.{}", stem, n, extension);
+    }
+    name
+}
+
+// マニフェストに書き出す1つのアイコンの情報
+#[derive(Serialize)]
+pub struct ManifestEntry {
+    pub filename: String,
+    pub recipe: Composition,
+}
+
+// 識別子からファイル名とレシピへの対応表
+// 識別子をキーにしたオブジェクトとして、リクエストの順番のまま書き出す
+pub struct Manifest(pub Vec<(String, ManifestEntry)>);
+
+impl Serialize for Manifest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(identifier, entry)| (identifier, entry)))
+    }
+}
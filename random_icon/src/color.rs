@@ -0,0 +1,227 @@
+use image::Rgb;
+use std::fmt;
+
+// 色コードの解析に失敗した理由
+#[derive(Debug, PartialEq)]
+pub enum ColorParseError {
+    // 桁数が 3 桁でも 6 桁でもない
+    InvalidLength(String),
+    // 16進数でない文字を含む
+    InvalidDigit(String),
+}
+
+impl fmt::Display for ColorParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorParseError::InvalidLength(hex) => write!(
+                f,
+                "invalid color \"{}\": expected 3 or 6 hex digits like ff0000",
+                hex
+            ),
+            ColorParseError::InvalidDigit(hex) => {
+                write!(f, "invalid color \"{}\": not a hex color code", hex)
+            }
+        }
+    }
+}
+
+// 16進数の色コードをRGBに変換する関数
+// 先頭の # は省略でき、fff のような 3 桁の省略形も受け付ける
+pub fn parse_hex_color(hex: &str) -> Result<Rgb<u8>, ColorParseError> {
+    let digits = hex.trim();
+    let digits = digits.strip_prefix('#').unwrap_or(digits);
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ColorParseError::InvalidDigit(hex.to_string()));
+    }
+    let expanded: String = match digits.len() {
+        3 => digits.chars().flat_map(|c| [c, c]).collect(),
+        6 => digits.to_string(),
+        _ => return Err(ColorParseError::InvalidLength(hex.to_string())),
+    };
+
+    let channel = |i: usize| u8::from_str_radix(&expanded[i..i + 2], 16);
+    match (channel(0), channel(2), channel(4)) {
+        (Ok(r), Ok(g), Ok(b)) => Ok(Rgb([r, g, b])),
+        _ => Err(ColorParseError::InvalidDigit(hex.to_string())),
+    }
+}
+
+// カンマ区切りの色コードの一覧を解析する関数
+pub fn parse_color_list(list: &str) -> Result<Vec<Rgb<u8>>, ColorParseError> {
+    list.split(',').map(parse_hex_color).collect()
+}
+
+// RGBを16進数の色コードに変換する関数
+pub fn to_hex(color: Rgb<u8>) -> String {
+    let [r, g, b] = color.0;
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+// 2つの色の知覚的な差 (CIE76 の ΔE) を求める関数
+pub fn delta_e(a: Rgb<u8>, b: Rgb<u8>) -> f32 {
+    let [l1, a1, b1] = to_lab(a);
+    let [l2, a2, b2] = to_lab(b);
+    ((l1 - l2).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)).sqrt()
+}
+
+// sRGB の値を線形の値 (0.0 から 1.0) に変換する関数
+fn linearize(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// sRGB を CIELAB (D65) に変換する関数
+fn to_lab(color: Rgb<u8>) -> [f32; 3] {
+    let [r, g, b] = color.0.map(linearize);
+
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let f = |t: f32| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+// 2つの色のコントラスト比 (WCAG) を求める関数
+// 1.0 (同じ明るさ) から 21.0 (白と黒) までの値になる
+pub fn contrast_ratio(a: Rgb<u8>, b: Rgb<u8>) -> f32 {
+    let luminance = |color: Rgb<u8>| {
+        let [r, g, b] = color.0.map(linearize);
+        0.2126 * r + 0.7152 * g + 0.0722 * b
+    };
+    let (la, lb) = (luminance(a), luminance(b));
+    (la.max(lb) + 0.05) / (la.min(lb) + 0.05)
+}
+
+// 明度と彩度を保ったまま色相を回転させる関数
+pub fn rotate_hue(color: Rgb<u8>, degrees: f32) -> Rgb<u8> {
+    let [r, g, b] = color.0.map(|c| c as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    let chroma = max - min;
+    if chroma <= 0.0 {
+        return color;
+    }
+
+    // RGB から HSL の色相を求める
+    let hue = if max == r {
+        ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    } * 60.0;
+    let hue = (hue + degrees).rem_euclid(360.0) / 60.0;
+
+    // 同じ彩度と明度のまま RGB に戻す
+    let x = chroma * (1.0 - (hue.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    Rgb([r, g, b].map(|c| ((c + m) * 255.0).round().clamp(0.0, 255.0) as u8))
+}
+
+// 色を16進数の色コードとして読み書きするための serde の with 用モジュール
+pub mod hex {
+    use image::Rgb;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &Rgb<u8>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::to_hex(*color))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Rgb<u8>, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        super::parse_hex_color(&hex).map_err(de::Error::custom)
+    }
+}
+
+// 省略できる色を読み書きするための serde の with 用モジュール
+pub mod hex_option {
+    use image::Rgb;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        color: &Option<Rgb<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match color {
+            Some(color) => super::hex::serialize(color, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Rgb<u8>>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(hex) => super::parse_hex_color(&hex)
+                .map(Some)
+                .map_err(de::Error::custom),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!(parse_hex_color("#ff8000"), Ok(Rgb([255, 128, 0])));
+        assert_eq!(parse_hex_color("FF8000"), Ok(Rgb([255, 128, 0])));
+        assert_eq!(parse_hex_color(" #aBc "), Ok(Rgb([0xaa, 0xbb, 0xcc])));
+        assert_eq!(parse_hex_color("f00"), Ok(Rgb([255, 0, 0])));
+    }
+
+    #[test]
+    fn rejects_invalid_hex_colors() {
+        for hex in ["", "#", "ff00", "#ff00000", "#f0"] {
+            assert_eq!(
+                parse_hex_color(hex),
+                Err(ColorParseError::InvalidLength(hex.to_string()))
+            );
+        }
+        for hex in ["gg0000", "#ff 000", "##f00", "+f0000", "ｆ00"] {
+            assert_eq!(
+                parse_hex_color(hex),
+                Err(ColorParseError::InvalidDigit(hex.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn parses_color_lists_and_round_trips() {
+        let colors = parse_color_list("#000,fff, 123456").unwrap();
+        assert_eq!(
+            colors,
+            vec![
+                Rgb([0, 0, 0]),
+                Rgb([255, 255, 255]),
+                Rgb([0x12, 0x34, 0x56])
+            ]
+        );
+        assert!(parse_color_list("#000,,fff").is_err());
+        assert_eq!(to_hex(Rgb([0x12, 0x34, 0x56])), "#123456");
+        assert_eq!(parse_hex_color(&to_hex(Rgb([1, 2, 3]))), Ok(Rgb([1, 2, 3])));
+    }
+}
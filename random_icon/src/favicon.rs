@@ -0,0 +1,90 @@
+use crate::color::to_hex;
+use crate::{archive, encode_image, flatten, raster, Composition, OutputFormat};
+use image::codecs::ico::{IcoEncoder, IcoFrame};
+use image::{ColorType, Rgb};
+use serde_json::json;
+use std::error::Error;
+
+// favicon.ico に含める画像のサイズ
+const ICO_SIZES: [u32; 3] = [16, 32, 48];
+// iOS のホーム画面用アイコンのサイズ
+const APPLE_TOUCH_ICON_SIZE: u32 = 180;
+// Android とウェブアプリマニフェスト用のアイコンのサイズ
+const ANDROID_ICON_SIZES: [u32; 2] = [192, 512];
+
+// 構図から favicon 一式を描画して ZIP にまとめる関数
+// name はウェブアプリマニフェストのアプリ名に使う
+pub fn favicon_pack(
+    composition: &Composition,
+    name: &str,
+    antialias: bool,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut files = vec![(
+        "favicon.ico".to_string(),
+        favicon_ico(composition, antialias)?,
+    )];
+
+    // iOS は透明な部分を黒く塗るので、背景が透明なら白い背景に重ねる
+    let img = raster::draw_icon(composition, APPLE_TOUCH_ICON_SIZE, antialias);
+    let mut buffer = Vec::new();
+    flatten(&img, Rgb([255, 255, 255])).write_to(
+        &mut std::io::Cursor::new(&mut buffer),
+        image::ImageOutputFormat::Png,
+    )?;
+    files.push(("apple-touch-icon.png".to_string(), buffer));
+
+    for size in ANDROID_ICON_SIZES {
+        let img = raster::draw_icon(composition, size, antialias);
+        files.push((
+            format!("android-chrome-{0}x{0}.png", size),
+            encode_image(&img, OutputFormat::Png)?,
+        ));
+    }
+
+    files.push((
+        "site.webmanifest".to_string(),
+        web_manifest(composition, name)?,
+    ));
+
+    Ok(archive::zip_files(&files)?)
+}
+
+// 複数のサイズの画像を含む ICO を作る関数
+// 小さいサイズでも縮小せずにそのサイズで描画する
+fn favicon_ico(composition: &Composition, antialias: bool) -> Result<Vec<u8>, Box<dyn Error>> {
+    let frames = ICO_SIZES
+        .iter()
+        .map(|&size| {
+            let img = raster::draw_icon(composition, size, antialias);
+            IcoFrame::as_png(img.as_raw(), size, size, ColorType::Rgba8)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut buffer = Vec::new();
+    IcoEncoder::new(&mut buffer).encode_images(&frames)?;
+    Ok(buffer)
+}
+
+// ウェブアプリマニフェストを作る関数
+// テーマカラーには構図の背景色を使い、背景が透明なら白にする
+fn web_manifest(composition: &Composition, name: &str) -> serde_json::Result<Vec<u8>> {
+    let theme_color = to_hex(composition.background.unwrap_or(Rgb([255, 255, 255])));
+    let icons: Vec<_> = ANDROID_ICON_SIZES
+        .iter()
+        .map(|size| {
+            json!({
+                "src": format!("/android-chrome-{0}x{0}.png", size),
+                "sizes": format!("{0}x{0}", size),
+                "type": "image/png",
+            })
+        })
+        .collect();
+    serde_json::to_vec_pretty(&json!({
+        "name": name,
+        "short_name": name,
+        "icons": icons,
+        "theme_color": theme_color,
+        "background_color": theme_color,
+        "display": "standalone",
+    }))
+}
@@ -0,0 +1,186 @@
+use crate::color::rotate_hue;
+use crate::other_color;
+use image::Rgb;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::{PI, SQRT_2};
+
+// グラデーション
+// 塗りの色 (図形の color や背景色) から to の色へ変わる
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Gradient {
+    // angle の向きに変わる
+    // 図形では図形の向きからの角度で、図形と一緒に回る
+    Linear {
+        angle: f32,
+        #[serde(with = "crate::color::hex")]
+        to: Rgb<u8>,
+    },
+    // 中心から外側に向かって変わる
+    Radial {
+        #[serde(with = "crate::color::hex")]
+        to: Rgb<u8>,
+    },
+}
+
+impl Gradient {
+    // 変わった先の色
+    pub fn to(self) -> Rgb<u8> {
+        match self {
+            Gradient::Linear { to, .. } | Gradient::Radial { to } => to,
+        }
+    }
+
+    // 値が描画できる範囲にあるか確かめる関数
+    pub fn is_finite(self) -> bool {
+        match self {
+            Gradient::Linear { angle, .. } => angle.is_finite(),
+            Gradient::Radial { .. } => true,
+        }
+    }
+
+    // 変わった先の色の色相を回転させる関数
+    pub fn rotate_hue(&mut self, degrees: f32) {
+        match self {
+            Gradient::Linear { to, .. } | Gradient::Radial { to } => *to = rotate_hue(*to, degrees),
+        }
+    }
+
+    // 図形の塗りで、中心と収まる円の半径、図形の向きから色の変わり方を求める関数
+    // 線形なら収まる円の端から端まで、放射なら中心から収まる円の周まで変わる
+    pub fn shape_ramp(self, center: (f32, f32), radius: f32, angle: f32, mirrored: bool) -> Ramp {
+        match self {
+            Gradient::Linear { angle: offset, .. } => {
+                let direction = angle + offset;
+                // 裏返した図形では向きも裏返す
+                let direction = if mirrored { PI - direction } else { direction };
+                Ramp::linear(center, radius, direction)
+            }
+            Gradient::Radial { .. } => Ramp::Radial { center, radius },
+        }
+    }
+
+    // 一辺が size の背景の塗りで、色の変わり方を求める関数
+    // 線形なら四隅のうち両端の2つで、放射なら四隅で端の色になる
+    pub fn background_ramp(self, size: f32) -> Ramp {
+        let center = (size / 2.0, size / 2.0);
+        match self {
+            Gradient::Linear { angle, .. } => {
+                let (sin, cos) = angle.sin_cos();
+                Ramp::linear(center, size / 2.0 * (sin.abs() + cos.abs()), angle)
+            }
+            Gradient::Radial { .. } => Ramp::Radial {
+                center,
+                radius: size / 2.0 * SQRT_2,
+            },
+        }
+    }
+}
+
+// 色の変わり方
+// 描画先の座標で、始まりの色から終わりの色へ変わる範囲を表す
+pub enum Ramp {
+    Linear { start: (f32, f32), end: (f32, f32) },
+    Radial { center: (f32, f32), radius: f32 },
+}
+
+impl Ramp {
+    // 中心から direction の向きに half_length だけ離れた所までで変わる線形の変わり方
+    fn linear(center: (f32, f32), half_length: f32, direction: f32) -> Ramp {
+        let (sin, cos) = direction.sin_cos();
+        Ramp::Linear {
+            start: (center.0 - cos * half_length, center.1 - sin * half_length),
+            end: (center.0 + cos * half_length, center.1 + sin * half_length),
+        }
+    }
+
+    // 座標の色が始まりの色から終わりの色へどれだけ変わったか (0.0 から 1.0) を求める関数
+    // 範囲の外は端の色のまま (SVG の spreadMethod="pad" と同じ)
+    pub fn position(&self, x: f32, y: f32) -> f32 {
+        let t = match *self {
+            Ramp::Linear { start, end } => {
+                let (ex, ey) = (end.0 - start.0, end.1 - start.1);
+                let length_squared = ex * ex + ey * ey;
+                if length_squared <= 0.0 {
+                    return 0.0;
+                }
+                ((x - start.0) * ex + (y - start.1) * ey) / length_squared
+            }
+            Ramp::Radial { center, radius } => {
+                if radius <= 0.0 {
+                    return 0.0;
+                }
+                (x - center.0).hypot(y - center.1) / radius
+            }
+        };
+        t.clamp(0.0, 1.0)
+    }
+}
+
+// グラデーションの種類の選び方
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GradientStyle {
+    Linear,
+    Radial,
+    // 塗りごとに線形か放射かを選ぶ
+    Mixed,
+}
+
+impl GradientStyle {
+    // 名前からグラデーションの種類の選び方を選ぶ関数
+    pub fn from_name(name: &str) -> Option<GradientStyle> {
+        match name {
+            "linear" => Some(GradientStyle::Linear),
+            "radial" => Some(GradientStyle::Radial),
+            "mixed" => Some(GradientStyle::Mixed),
+            _ => None,
+        }
+    }
+
+    // from の色から始まるランダムなグラデーションを作る関数
+    pub fn random<R: Rng>(self, rng: &mut R, colors: &[Rgb<u8>], from: Rgb<u8>) -> Gradient {
+        let to = other_color(rng, colors, from);
+        let linear = match self {
+            GradientStyle::Linear => true,
+            GradientStyle::Radial => false,
+            GradientStyle::Mixed => rng.gen_bool(0.5),
+        };
+        if linear {
+            Gradient::Linear {
+                angle: rng.gen_range(0.0..2.0 * PI),
+                to,
+            }
+        } else {
+            Gradient::Radial { to }
+        }
+    }
+}
+
+// グラデーションで塗るもの
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GradientTarget {
+    All,
+    Background,
+    Shapes,
+}
+
+impl GradientTarget {
+    // 名前からグラデーションで塗るものを選ぶ関数
+    pub fn from_name(name: &str) -> Option<GradientTarget> {
+        match name {
+            "all" => Some(GradientTarget::All),
+            "background" => Some(GradientTarget::Background),
+            "shapes" => Some(GradientTarget::Shapes),
+            _ => None,
+        }
+    }
+
+    pub fn background(self) -> bool {
+        self != GradientTarget::Shapes
+    }
+
+    pub fn shapes(self) -> bool {
+        self != GradientTarget::Background
+    }
+}
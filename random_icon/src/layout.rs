@@ -0,0 +1,146 @@
+use crate::{ShapeSpec, ICON_SIZE, MAX_SHAPE_SIZE, MIN_SHAPE_SIZE};
+use rand::Rng;
+use std::f32::consts::PI;
+use std::ops::Range;
+
+// 散らばらせる図形を置く場所を探す回数
+const SCATTER_ATTEMPTS: u32 = 200;
+
+// 図形の並べ方
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layout {
+    // 四隅と中央の5か所 (以前からの並べ方)
+    Corners,
+    // N×N の格子
+    Grid(u32),
+    // 中央を囲む輪
+    Ring(u32),
+    // 重ならないように散らばらせる
+    Scatter(u32),
+    // 中央に1つ
+    Emblem,
+}
+
+impl Layout {
+    // layout パラメータを読み込む関数
+    // grid:4 のように : の後に図形の数を指定できる
+    pub fn parse(value: &str) -> Result<Layout, String> {
+        let (name, count) = match value.split_once(':') {
+            Some((name, count)) => {
+                let count = count
+                    .parse::<u32>()
+                    .map_err(|_| format!("invalid shape count in layout: {}", value))?;
+                (name, Some(count))
+            }
+            None => (value, None),
+        };
+        // 図形の数の範囲と省略したときの値
+        let (variant, range, default): (fn(u32) -> Layout, Range<u32>, u32) = match name {
+            "corners" | "emblem" => {
+                if count.is_some() {
+                    return Err(format!("layout {} does not take a shape count", name));
+                }
+                return Ok(if name == "corners" {
+                    Layout::Corners
+                } else {
+                    Layout::Emblem
+                });
+            }
+            "grid" => (Layout::Grid, 1..9, 3),
+            "ring" => (Layout::Ring, 3..17, 6),
+            "scatter" => (Layout::Scatter, 1..33, 8),
+            _ => return Err(format!("unknown layout: {}", name)),
+        };
+        let count = count.unwrap_or(default);
+        if !range.contains(&count) {
+            return Err(format!(
+                "layout {} takes a count between {} and {}: {}",
+                name,
+                range.start,
+                range.end - 1,
+                count
+            ));
+        }
+        Ok(variant(count))
+    }
+
+    // 場所が決まっている並べ方の、図形の中心と大きさの範囲を求める関数
+    // 散らばらせる場合は図形の大きさによって場所が変わるので None を返す
+    pub fn placements(self) -> Option<(Vec<(f32, f32)>, ShapeSizes)> {
+        let icon = ICON_SIZE as f32;
+        let center = ((ICON_SIZE / 2) as f32, (ICON_SIZE / 2) as f32);
+        match self {
+            Layout::Corners => {
+                let last = (ICON_SIZE - 1) as f32;
+                let positions = vec![(0.0, 0.0), (last, 0.0), (0.0, last), (last, last), center];
+                Some((positions, ShapeSizes::Size(MIN_SHAPE_SIZE..MAX_SHAPE_SIZE)))
+            }
+            Layout::Grid(n) => {
+                let cell = icon / n as f32;
+                let positions = (0..n * n)
+                    .map(|i| {
+                        let (column, row) = ((i % n) as f32, (i / n) as f32);
+                        ((column + 0.5) * cell, (row + 0.5) * cell)
+                    })
+                    .collect();
+                Some((positions, ShapeSizes::Radius(cell * 0.3..cell * 0.48)))
+            }
+            Layout::Ring(n) => {
+                let radius = icon * 0.3;
+                let positions = (0..n)
+                    .map(|i| {
+                        let theta = -PI / 2.0 + 2.0 * PI * i as f32 / n as f32;
+                        (
+                            center.0 + radius * theta.cos(),
+                            center.1 + radius * theta.sin(),
+                        )
+                    })
+                    .collect();
+                // 隣り合う図形が重ならないように、中心の間隔の半分を超えない大きさにする
+                let max_radius = (radius * (PI / n as f32).sin()).min(icon * 0.18);
+                Some((positions, ShapeSizes::Radius(max_radius * 0.7..max_radius)))
+            }
+            Layout::Scatter(_) => None,
+            Layout::Emblem => Some((vec![center], ShapeSizes::Radius(icon * 0.3..icon * 0.4))),
+        }
+    }
+}
+
+// 図形の大きさの選び方
+pub enum ShapeSizes {
+    // size をこの範囲から選ぶ
+    Size(Range<u32>),
+    // 図形がすっぽり収まる円の半径がこの範囲に入るように size を選ぶ
+    Radius(Range<f32>),
+}
+
+// 散らばらせる図形の、収まる円の半径の範囲を求める関数
+// 図形の数が多いほど小さくする
+pub fn scatter_sizes(count: u32) -> ShapeSizes {
+    let max_radius = (ICON_SIZE as f32 / 2.0 * (0.6 / count as f32).sqrt()).clamp(25.0, 110.0);
+    ShapeSizes::Radius(max_radius * 0.5..max_radius)
+}
+
+// 散らばらせる図形を、アイコンの内側で先に置いた図形と重ならない場所に置く関数
+// 何度試しても場所が見つからなければ None を返す
+pub fn scatter_position<R: Rng>(
+    rng: &mut R,
+    radius: f32,
+    placed: &[ShapeSpec],
+) -> Option<(f32, f32)> {
+    let icon = ICON_SIZE as f32;
+    if radius * 2.0 >= icon {
+        return None;
+    }
+    (0..SCATTER_ATTEMPTS).find_map(|_| {
+        let position = (
+            rng.gen_range(radius..icon - radius),
+            rng.gen_range(radius..icon - radius),
+        );
+        let overlaps = placed.iter().any(|shape| {
+            let (dx, dy) = (shape.position.0 - position.0, shape.position.1 - position.1);
+            dx.hypot(dy) < shape.radius() + radius
+        });
+        (!overlaps).then_some(position)
+    })
+}
@@ -0,0 +1,1698 @@
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::error::InternalError;
+use actix_web::http::header;
+use actix_web::middleware::Logger;
+use actix_web::{web, App, HttpMessage, HttpRequest, HttpResponse, HttpServer, Responder};
+use futures::future::{ok, Ready};
+use futures::Future;
+use gradient::{Gradient, GradientStyle, GradientTarget};
+use image::{ImageOutputFormat, Rgb, RgbImage, RgbaImage};
+use layout::{Layout, ShapeSizes};
+use log::{debug, error, info, warn};
+use mask::Mask;
+use pattern::{Pattern, PatternStyle};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use shape::{Geometry, Shape};
+use std::collections::HashSet;
+use std::f32::consts::PI;
+use std::io::Cursor;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use symmetry::Symmetry;
+use uuid::Uuid;
+
+mod animation;
+mod archive;
+mod batch;
+mod color;
+mod favicon;
+mod gradient;
+mod layout;
+mod mask;
+mod palette;
+mod path;
+mod pattern;
+mod raster;
+pub mod shape;
+mod sprite;
+mod svg;
+mod symmetry;
+pub mod text;
+
+// 図形の大きさはこのサイズを基準に決め、出力サイズに合わせて拡大縮小する
+const ICON_SIZE: u32 = 500;
+const MIN_ICON_SIZE: u32 = 16;
+const MAX_ICON_SIZE: u32 = 2048;
+// ICO 形式で書き出せる最大のサイズ
+const MAX_ICO_SIZE: u32 = 256;
+const MIN_SHAPE_SIZE: u32 = 150;
+const MAX_SHAPE_SIZE: u32 = 250;
+// レシピに含められる図形の数と大きさの上限
+const MAX_RECIPE_SHAPES: usize = 256;
+const MAX_RECIPE_SHAPE_SIZE: u32 = ICON_SIZE * 4;
+// POST /render でレシピを描画するときに図形を塗る画素の数の合計の上限 (アニメーションでは全コマの合計)
+// 生成したアイコンはクエリパラメータの範囲で手間が決まるので、この上限は確かめない
+// そのため format=json で返したレシピでも、大きく描いたりアニメーションにしたりすると超えることがある
+const MAX_RECIPE_PIXELS: f32 = 80.0 * 1024.0 * 1024.0;
+// JSON のリクエストボディの大きさの上限 (識別子の多いバッチを受け取れるようにする)
+const MAX_JSON_BODY_SIZE: usize = 1024 * 1024;
+// safe_zone で構図を縮められる下限
+const MIN_SAFE_ZONE: f32 = 0.1;
+// 輪郭の線の幅の範囲と、線だけで描くときに幅を省略した場合の値
+const MIN_STROKE_WIDTH: f32 = 1.0;
+const MAX_STROKE_WIDTH: f32 = 100.0;
+const DEFAULT_STROKE_WIDTH: f32 = 12.0;
+// 模様の繰り返しの間隔 (ICON_SIZE 四方を基準にした値)
+const MIN_PATTERN_SPACING: f32 = 4.0;
+const MAX_PATTERN_SPACING: f32 = 250.0;
+const DEFAULT_PATTERN_SPACING: f32 = 40.0;
+// 重ねる文字の色を選ぶために構図を描く大きさ
+const OVERLAY_SAMPLE_SIZE: u32 = 128;
+
+// カスタムミドルウェア
+struct RequestId;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = RequestIdMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestIdMiddleware { service })
+    }
+}
+
+struct RequestIdMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let request_id = Uuid::new_v4();
+        req.extensions_mut().insert(request_id);
+        debug!("Request ID {}: Received request", request_id);
+
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let res = fut.await?;
+            let request_id = res.request().extensions().get::<Uuid>().cloned().unwrap();
+            debug!("Request ID {}: Response sent", request_id);
+
+            Ok(res.map_body(|_, body| body))
+        })
+    }
+}
+
+// アイコン生成のクエリパラメータ
+#[derive(Deserialize)]
+struct IconQuery {
+    // 同じ値なら常に同じアイコンを生成する
+    #[serde(alias = "id")]
+    seed: Option<String>,
+    // 出力する画像の一辺のピクセル数
+    size: Option<u32>,
+    // 出力形式 (png, svg, json, jpeg, webp, gif, bmp, ico)
+    // 省略すると Accept ヘッダーから選ぶ
+    format: Option<String>,
+    // false なら図形の縁をぼかさずに描画する
+    antialias: Option<bool>,
+    // true なら背景を透明にする
+    transparent: Option<bool>,
+    // 図形の不透明度 (0.0 から 1.0)
+    opacity: Option<f32>,
+    // 色を選ぶパレットの名前
+    palette: Option<String>,
+    // パレットの代わりに使うカンマ区切りの色コード
+    colors: Option<String>,
+    // 背景色を固定する色コード
+    background: Option<String>,
+    // 背景や重なる図形との間に確保する色差 (CIELAB の ΔE)
+    contrast: Option<f32>,
+    // 図形の動かし方 (rotate, pulse, colors)
+    // 指定すると GIF と PNG はアニメーションになる
+    animate: Option<String>,
+    // アニメーション1周のコマ数
+    frames: Option<u32>,
+    // アニメーション1周にかける時間 (ミリ秒)
+    duration: Option<u32>,
+    // 選ぶ図形の種類のカンマ区切りの名前
+    shapes: Option<String>,
+    // 図形の並べ方 (corners, grid, ring, scatter, emblem)
+    layout: Option<String>,
+    // 構図の対称性 (horizontal, vertical, rot4, rot6)
+    symmetry: Option<String>,
+    // アイコンを切り抜く形 (circle, rounded, squircle)
+    mask: Option<String>,
+    // 構図を中心に向かって縮める割合 (0.1 から 1.0)
+    safe_zone: Option<f32>,
+    // アイコンの中央に重ねる文字列 (イニシャルや1文字の漢字、仮名)
+    text: Option<String>,
+    // 重ねる文字列の色コード
+    // 省略すると下の構図とのコントラストが高い色を選ぶ
+    text_color: Option<String>,
+    // グラデーションの種類 (linear, radial, mixed)
+    gradient: Option<String>,
+    // グラデーションで塗るもの (all, background, shapes)
+    gradient_target: Option<String>,
+    // 図形の輪郭に描く線の幅 (ICON_SIZE 四方を基準にした値)
+    stroke: Option<f32>,
+    // 輪郭の線の色コード
+    stroke_color: Option<String>,
+    // true なら図形を塗らずに輪郭の線だけを描く
+    line_art: Option<bool>,
+    // 図形に描く模様 (stripes, dots, checker, hatch, mixed)
+    pattern: Option<String>,
+    // 模様の繰り返しの間隔 (ICON_SIZE 四方を基準にした値)
+    pattern_spacing: Option<f32>,
+}
+
+// 出力形式
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OutputFormat {
+    Png,
+    Svg,
+    // 構図をレシピとして返す
+    Json,
+    Jpeg,
+    Webp,
+    Gif,
+    Bmp,
+    Ico,
+}
+
+impl OutputFormat {
+    fn from_name(name: &str) -> Option<OutputFormat> {
+        match name {
+            "png" => Some(OutputFormat::Png),
+            "svg" => Some(OutputFormat::Svg),
+            "json" => Some(OutputFormat::Json),
+            "jpeg" | "jpg" => Some(OutputFormat::Jpeg),
+            "webp" => Some(OutputFormat::Webp),
+            "gif" => Some(OutputFormat::Gif),
+            "bmp" => Some(OutputFormat::Bmp),
+            "ico" => Some(OutputFormat::Ico),
+            _ => None,
+        }
+    }
+
+    fn from_mime(mime: &str) -> Option<OutputFormat> {
+        match mime {
+            "image/png" => Some(OutputFormat::Png),
+            "image/svg+xml" => Some(OutputFormat::Svg),
+            "application/json" => Some(OutputFormat::Json),
+            "image/jpeg" => Some(OutputFormat::Jpeg),
+            "image/webp" => Some(OutputFormat::Webp),
+            "image/gif" => Some(OutputFormat::Gif),
+            "image/bmp" => Some(OutputFormat::Bmp),
+            "image/x-icon" | "image/vnd.microsoft.icon" => Some(OutputFormat::Ico),
+            _ => None,
+        }
+    }
+
+    fn mime(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "image/png",
+            OutputFormat::Svg => "image/svg+xml",
+            OutputFormat::Json => "application/json",
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::Webp => "image/webp",
+            OutputFormat::Gif => "image/gif",
+            OutputFormat::Bmp => "image/bmp",
+            OutputFormat::Ico => "image/x-icon",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Svg => "svg",
+            OutputFormat::Json => "json",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Webp => "webp",
+            OutputFormat::Gif => "gif",
+            OutputFormat::Bmp => "bmp",
+            OutputFormat::Ico => "ico",
+        }
+    }
+}
+
+// Accept ヘッダーから出力形式を選ぶ関数
+// ヘッダーがなければ PNG を返し、対応できる形式がひとつもなければ None を返す
+fn accept_format(req: &HttpRequest) -> Option<OutputFormat> {
+    let accept = match req.headers().get(header::ACCEPT) {
+        Some(value) => value.to_str().unwrap_or(""),
+        None => return Some(OutputFormat::Png),
+    };
+    if accept.trim().is_empty() {
+        return Some(OutputFormat::Png);
+    }
+
+    let ranges: Vec<(String, f32)> = accept
+        .split(',')
+        .map(|item| {
+            let mut params = item.split(';');
+            let mime = params.next().unwrap_or("").trim().to_ascii_lowercase();
+            // q = 0.5 のように = の前後に空白があっても重みとして読む
+            let quality = params
+                .filter_map(|param| param.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                .and_then(|(_, q)| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (mime, quality)
+        })
+        .collect();
+    // q=0 で明示的に拒否された形式はワイルドカードでも選ばない
+    let refused: Vec<OutputFormat> = ranges
+        .iter()
+        .filter(|(_, quality)| *quality <= 0.0)
+        .filter_map(|(mime, _)| OutputFormat::from_mime(mime))
+        .collect();
+
+    let mut best: Option<(f32, OutputFormat)> = None;
+    for (mime, quality) in &ranges {
+        let format = match mime.as_str() {
+            "*/*" | "image/*" => [OutputFormat::Png, OutputFormat::Webp, OutputFormat::Jpeg]
+                .into_iter()
+                .find(|format| !refused.contains(format)),
+            "application/*" => Some(OutputFormat::Json).filter(|format| !refused.contains(format)),
+            mime => OutputFormat::from_mime(mime),
+        };
+        // 同じ重みなら先に書かれたものを優先する
+        if let Some(format) = format {
+            if *quality > 0.0 && best.is_none_or(|(best_quality, _)| *quality > best_quality) {
+                best = Some((*quality, format));
+            }
+        }
+    }
+    best.map(|(_, format)| format)
+}
+
+// クエリパラメータを検証した結果
+struct IconOptions {
+    size: u32,
+    // None なら Accept ヘッダーで決める
+    format: Option<OutputFormat>,
+    antialias: bool,
+    transparent: bool,
+    opacity: f32,
+    colors: Vec<Rgb<u8>>,
+    background: Option<Rgb<u8>>,
+    contrast: Option<f32>,
+    // None なら静止画
+    animation: Option<animation::Animation>,
+    // ランダムに選ぶ図形の種類
+    shapes: Vec<&'static dyn Shape>,
+    layout: Layout,
+    // None なら対称にしない
+    symmetry: Option<Symmetry>,
+    // None なら切り抜かない
+    mask: Option<Mask>,
+    // 1.0 なら縮めない
+    safe_zone: f32,
+    // None なら文字列を重ねない
+    text: Option<String>,
+    // None なら構図に合わせて選ぶ
+    text_color: Option<Rgb<u8>>,
+    // None なら単色で塗る
+    gradient: Option<(GradientStyle, GradientTarget)>,
+    // None なら輪郭の線を描かない
+    stroke_width: Option<f32>,
+    // None なら線だけのときは図形の色、塗るときは背景色で描く
+    stroke_color: Option<Rgb<u8>>,
+    line_art: bool,
+    // None なら模様を描かない
+    pattern: Option<(PatternStyle, f32)>,
+}
+
+// クエリパラメータを検証する関数
+fn icon_options(query: &IconQuery) -> Result<IconOptions, String> {
+    let size = match query.size {
+        None => ICON_SIZE,
+        Some(size) if (MIN_ICON_SIZE..=MAX_ICON_SIZE).contains(&size) => size,
+        Some(size) => {
+            return Err(format!(
+                "size must be between {} and {}: {}",
+                MIN_ICON_SIZE, MAX_ICON_SIZE, size
+            ))
+        }
+    };
+    let format = match &query.format {
+        None => None,
+        Some(name) => Some(
+            OutputFormat::from_name(name).ok_or_else(|| format!("unsupported format: {}", name))?,
+        ),
+    };
+
+    let opacity = match query.opacity {
+        None => 1.0,
+        Some(opacity) if (0.0..=1.0).contains(&opacity) => opacity,
+        Some(opacity) => return Err(format!("opacity must be between 0.0 and 1.0: {}", opacity)),
+    };
+    let colors = match &query.colors {
+        Some(list) => color::parse_color_list(list).map_err(|e| e.to_string())?,
+        None => {
+            let name = query.palette.as_deref().unwrap_or(palette::DEFAULT_PALETTE);
+            let palette =
+                palette::find_palette(name).ok_or_else(|| format!("unknown palette: {}", name))?;
+            palette
+                .colors
+                .iter()
+                .map(|hex| color::parse_hex_color(hex))
+                .collect::<Result<_, _>>()
+                .map_err(|e| e.to_string())?
+        }
+    };
+    let background = match &query.background {
+        Some(hex) => Some(color::parse_hex_color(hex).map_err(|e| e.to_string())?),
+        None => None,
+    };
+    let contrast = match query.contrast {
+        None => None,
+        Some(delta_e) if (0.0..=100.0).contains(&delta_e) => Some(delta_e),
+        Some(delta_e) => return Err(format!("contrast must be between 0 and 100: {}", delta_e)),
+    };
+    let registry = shape::registry();
+    let shapes: Vec<&dyn Shape> = match &query.shapes {
+        None => {
+            // 組み込みの図形を外した一覧なら、登録されているすべての図形から選ぶ
+            let defaults: Vec<&dyn Shape> = shape::DEFAULT_SHAPES
+                .iter()
+                .filter_map(|name| registry.find(name))
+                .collect();
+            if defaults.is_empty() {
+                registry.shapes().collect()
+            } else {
+                defaults
+            }
+        }
+        Some(list) => list
+            .split(',')
+            .map(|name| {
+                registry
+                    .find(name.trim())
+                    .ok_or_else(|| format!("unknown shape: {}", name))
+            })
+            .collect::<Result<_, _>>()?,
+    };
+    if shapes.is_empty() {
+        return Err("no shapes are available".to_string());
+    }
+    // 同じ名前を繰り返すとその図形が選ばれやすくなる
+    if shapes.len() > u8::MAX as usize {
+        return Err(format!("shapes can list at most {} names", u8::MAX));
+    }
+    let layout = match &query.layout {
+        None => Layout::Corners,
+        Some(value) => Layout::parse(value)?,
+    };
+    let symmetry = match &query.symmetry {
+        None => None,
+        Some(name) => {
+            Some(Symmetry::from_name(name).ok_or_else(|| format!("unknown symmetry: {}", name))?)
+        }
+    };
+    let mask = match &query.mask {
+        None => None,
+        Some(name) => Some(Mask::from_name(name).ok_or_else(|| format!("unknown mask: {}", name))?),
+    };
+    let safe_zone = match query.safe_zone {
+        None => mask.map_or(1.0, Mask::default_safe_zone),
+        Some(safe_zone) if (MIN_SAFE_ZONE..=1.0).contains(&safe_zone) => safe_zone,
+        Some(safe_zone) => {
+            return Err(format!(
+                "safe_zone must be between {} and 1.0: {}",
+                MIN_SAFE_ZONE, safe_zone
+            ))
+        }
+    };
+    if let Some(text) = &query.text {
+        text::check_overlay(text)?;
+    }
+    let text_color = match &query.text_color {
+        Some(hex) => Some(color::parse_hex_color(hex).map_err(|e| e.to_string())?),
+        None => None,
+    };
+    let gradient = match &query.gradient {
+        None => None,
+        Some(name) => {
+            let style = GradientStyle::from_name(name)
+                .ok_or_else(|| format!("unknown gradient: {}", name))?;
+            let target = match &query.gradient_target {
+                None => GradientTarget::All,
+                Some(name) => GradientTarget::from_name(name)
+                    .ok_or_else(|| format!("unknown gradient target: {}", name))?,
+            };
+            Some((style, target))
+        }
+    };
+    let line_art = query.line_art.unwrap_or(false);
+    let stroke_width = match query.stroke {
+        // 線だけで描くなら幅を省略できる
+        None if line_art => Some(DEFAULT_STROKE_WIDTH),
+        None => None,
+        Some(width) if (MIN_STROKE_WIDTH..=MAX_STROKE_WIDTH).contains(&width) => Some(width),
+        Some(width) => {
+            return Err(format!(
+                "stroke must be between {} and {}: {}",
+                MIN_STROKE_WIDTH, MAX_STROKE_WIDTH, width
+            ))
+        }
+    };
+    let stroke_color = match &query.stroke_color {
+        Some(hex) => Some(color::parse_hex_color(hex).map_err(|e| e.to_string())?),
+        None => None,
+    };
+    let pattern = match &query.pattern {
+        None => None,
+        Some(name) => {
+            let style = PatternStyle::from_name(name)
+                .ok_or_else(|| format!("unknown pattern: {}", name))?;
+            let spacing = query.pattern_spacing.unwrap_or(DEFAULT_PATTERN_SPACING);
+            if !(MIN_PATTERN_SPACING..=MAX_PATTERN_SPACING).contains(&spacing) {
+                return Err(format!(
+                    "pattern_spacing must be between {} and {}: {}",
+                    MIN_PATTERN_SPACING, MAX_PATTERN_SPACING, spacing
+                ));
+            }
+            Some((style, spacing))
+        }
+    };
+    let animation = match &query.animate {
+        None => None,
+        Some(name) => {
+            let motion = animation::Motion::from_name(name)
+                .ok_or_else(|| format!("unsupported animation: {}", name))?;
+            let frames = query.frames.unwrap_or(animation::DEFAULT_FRAMES);
+            if !(animation::MIN_FRAMES..=animation::MAX_FRAMES).contains(&frames) {
+                return Err(format!(
+                    "frames must be between {} and {}: {}",
+                    animation::MIN_FRAMES,
+                    animation::MAX_FRAMES,
+                    frames
+                ));
+            }
+            let duration = query.duration.unwrap_or(animation::DEFAULT_DURATION);
+            if !(animation::MIN_DURATION..=animation::MAX_DURATION).contains(&duration) {
+                return Err(format!(
+                    "duration must be between {} and {}: {}",
+                    animation::MIN_DURATION,
+                    animation::MAX_DURATION,
+                    duration
+                ));
+            }
+            if duration < frames * animation::MIN_FRAME_DELAY {
+                return Err(format!(
+                    "duration must be at least {} ms per frame: {} ms for {} frames",
+                    animation::MIN_FRAME_DELAY,
+                    duration,
+                    frames
+                ));
+            }
+            if size > animation::MAX_ANIMATION_SIZE {
+                return Err(format!(
+                    "animated icons can be at most {} pixels: {}",
+                    animation::MAX_ANIMATION_SIZE,
+                    size
+                ));
+            }
+            Some(animation::Animation {
+                motion,
+                frames,
+                duration,
+            })
+        }
+    };
+
+    Ok(IconOptions {
+        size,
+        format,
+        antialias: query.antialias.unwrap_or(true),
+        transparent: query.transparent.unwrap_or(false),
+        opacity,
+        colors,
+        background,
+        contrast,
+        animation,
+        shapes,
+        layout,
+        symmetry,
+        mask,
+        safe_zone,
+        text: query.text.clone(),
+        text_color,
+        gradient,
+        stroke_width,
+        stroke_color,
+        line_art,
+        pattern,
+    })
+}
+
+// 出力形式がアニメーションを書き出せるか確かめる関数
+fn check_animation(options: &IconOptions, format: OutputFormat) -> Result<(), String> {
+    match (&options.animation, format) {
+        (None, _) | (Some(_), OutputFormat::Gif | OutputFormat::Png) => Ok(()),
+        (Some(_), format) => Err(format!(
+            "animation is only available for gif and png, not {}",
+            format.extension()
+        )),
+    }
+}
+
+// アイコン生成エンドポイント
+async fn generate_icon(req: HttpRequest, query: web::Query<IconQuery>) -> impl Responder {
+    let request_id = request_id(&req);
+    debug!("Request ID {}: Icon generation started", request_id);
+
+    let options = match icon_options(&query) {
+        Ok(options) => options,
+        Err(message) => return bad_request(request_id, message),
+    };
+    let Some(format) = options.format.or_else(|| accept_format(&req)) else {
+        return not_acceptable(request_id);
+    };
+    if let Some(seed) = &query.seed {
+        debug!("Request ID {}: Seed specified: {}", request_id, seed);
+    }
+    let mut rng = seeded_rng(query.seed.as_deref());
+    let composition = compose_icon(&mut rng, &options, request_id);
+
+    let vary = options.format.is_none();
+    let mut response = icon_response(composition, options, format, request_id).await;
+    if vary {
+        response
+            .headers_mut()
+            .insert(header::VARY, header::HeaderValue::from_static("Accept"));
+    }
+    response
+}
+
+// 識別子ごとに固定のアイコンを返すエンドポイント
+// 識別子をシードとして扱うので /generate-icon?seed={identifier} と同じアイコンになる
+// 出力形式は拡張子で決まり、seed と format パラメータは無視する
+async fn identicon(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    query: web::Query<IconQuery>,
+) -> impl Responder {
+    let request_id = request_id(&req);
+    let (identifier, extension) = path.into_inner();
+    debug!(
+        "Request ID {}: Icon generation started for identifier: {}",
+        request_id, identifier
+    );
+
+    let Some(format) = OutputFormat::from_name(&extension) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let options = match icon_options(&query) {
+        Ok(options) => options,
+        Err(message) => return bad_request(request_id, message),
+    };
+    let mut rng = seeded_rng(Some(&identifier));
+    let composition = compose_icon(&mut rng, &options, request_id);
+
+    let mut response = icon_response(composition, options, format, request_id).await;
+    if response.status().is_success() {
+        // 識別子に対して結果が変わらないのでブラウザやCDNにキャッシュさせる
+        response.headers_mut().insert(
+            header::CACHE_CONTROL,
+            header::HeaderValue::from_static("public, max-age=86400"),
+        );
+    }
+    response
+}
+
+// レシピを受け取って描画するエンドポイント
+// size, format, antialias 以外のクエリパラメータは構図を選ぶためのものなので無視する
+async fn render_recipe(
+    req: HttpRequest,
+    query: web::Query<IconQuery>,
+    recipe: web::Json<Composition>,
+) -> impl Responder {
+    let request_id = request_id(&req);
+    debug!(
+        "Request ID {}: Rendering recipe with {} shapes",
+        request_id,
+        recipe.shapes.len()
+    );
+
+    let options = match icon_options(&query) {
+        Ok(options) => options,
+        Err(message) => return bad_request(request_id, message),
+    };
+    if let Err(message) = validate_recipe(&recipe) {
+        return bad_request(request_id, message);
+    }
+    let Some(format) = options.format.or_else(|| accept_format(&req)) else {
+        return not_acceptable(request_id);
+    };
+    if let Err(message) = check_render_cost(&recipe, &options, format) {
+        return bad_request(request_id, message);
+    }
+
+    let vary = options.format.is_none();
+    let mut response = icon_response(recipe.into_inner(), options, format, request_id).await;
+    if vary {
+        response
+            .headers_mut()
+            .insert(header::VARY, header::HeaderValue::from_static("Accept"));
+    }
+    response
+}
+
+// 識別子ごとに固定の favicon 一式を ZIP で返すエンドポイント
+// /icons/{identifier}.png と同じ構図を各サイズで描画する
+// seed, size, format パラメータは無視する
+async fn favicon_bundle(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<IconQuery>,
+) -> impl Responder {
+    let request_id = request_id(&req);
+    let identifier = path.into_inner();
+    debug!(
+        "Request ID {}: Favicon generation started for identifier: {}",
+        request_id, identifier
+    );
+
+    let options = match icon_options(&query) {
+        Ok(options) => options,
+        Err(message) => return bad_request(request_id, message),
+    };
+    let mut rng = seeded_rng(Some(&identifier));
+    let composition = compose_icon(&mut rng, &options, request_id);
+
+    // 複数の大きさを描画するので、ワーカーをふさがないように別のスレッドで作る
+    let result = web::block(move || {
+        favicon::favicon_pack(&composition, &identifier, options.antialias)
+            .map_err(|e| e.to_string())
+    })
+    .await;
+
+    match result {
+        Ok(Ok(buffer)) => {
+            info!("Request ID {}: Favicon generation completed", request_id);
+            HttpResponse::Ok()
+                .content_type("application/zip")
+                .insert_header((
+                    header::CONTENT_DISPOSITION,
+                    r#"attachment; filename="favicons.zip""#,
+                ))
+                .insert_header((header::CACHE_CONTROL, "public, max-age=86400"))
+                .body(buffer)
+        }
+        Ok(Err(e)) => {
+            error!(
+                "Request ID {}: Failed to build favicon bundle: {}",
+                request_id, e
+            );
+            HttpResponse::InternalServerError().body("Favicon generation failed")
+        }
+        Err(e) => {
+            error!(
+                "Request ID {}: Favicon generation aborted: {}",
+                request_id, e
+            );
+            HttpResponse::InternalServerError().body("Favicon generation failed")
+        }
+    }
+}
+
+// 複数のアイコンをまとめて生成して ZIP で返すエンドポイント
+// 各アイコンは /icons/{identifier}.{format} と同じで、manifest.json に識別子からファイル名とレシピへの対応を書き出す
+// format を省略すると PNG にし、seed パラメータは無視する
+async fn generate_batch(
+    req: HttpRequest,
+    query: web::Query<IconQuery>,
+    batch: web::Json<batch::BatchRequest>,
+) -> impl Responder {
+    let request_id = request_id(&req);
+    let options = match icon_options(&query) {
+        Ok(options) => options,
+        Err(message) => return bad_request(request_id, message),
+    };
+    let identifiers = match batch.into_inner().identifiers() {
+        Ok(identifiers) => identifiers,
+        Err(message) => return bad_request(request_id, message),
+    };
+    let format = options.format.unwrap_or(OutputFormat::Png);
+    if let Err(message) = check_animation(&options, format) {
+        return bad_request(request_id, message);
+    }
+    if let Some((size, frames)) = raster_work(&options, format) {
+        if let Err(message) = batch::check_batch_cost(identifiers.len(), size, frames) {
+            return bad_request(request_id, message);
+        }
+    }
+    debug!(
+        "Request ID {}: Batch generation started for {} icons",
+        request_id,
+        identifiers.len()
+    );
+
+    // 生成に時間がかかるのでワーカーをふさがないように別のスレッドで描画する
+    let result = web::block(move || -> Result<Vec<u8>, String> {
+        // アイコンのファイル名が manifest.json と重ならないように、先に使用済みにしておく
+        let mut used = HashSet::from([batch::MANIFEST_FILE_NAME.to_string()]);
+        let mut files = Vec::new();
+        let mut manifest = Vec::new();
+        for identifier in identifiers {
+            let mut rng = seeded_rng(Some(&identifier));
+            let composition = compose_icon(&mut rng, &options, request_id);
+            let filename = batch::file_name(&identifier, format.extension(), &mut used);
+            let body = render_icon(&composition, &options, format).map_err(|e| e.to_string())?;
+            files.push((filename.clone(), body));
+            manifest.push((
+                identifier,
+                batch::ManifestEntry {
+                    filename,
+                    recipe: composition,
+                },
+            ));
+        }
+        let manifest =
+            serde_json::to_vec_pretty(&batch::Manifest(manifest)).map_err(|e| e.to_string())?;
+        files.push((batch::MANIFEST_FILE_NAME.to_string(), manifest));
+        archive::zip_files(&files).map_err(|e| e.to_string())
+    })
+    .await;
+
+    match result {
+        Ok(Ok(buffer)) => {
+            info!("Request ID {}: Batch generation completed", request_id);
+            HttpResponse::Ok()
+                .content_type("application/zip")
+                .insert_header((
+                    header::CONTENT_DISPOSITION,
+                    r#"attachment; filename="icons.zip""#,
+                ))
+                .body(buffer)
+        }
+        Ok(Err(e)) => {
+            error!("Request ID {}: Failed to build batch: {}", request_id, e);
+            HttpResponse::InternalServerError().body("Image generation failed")
+        }
+        Err(e) => {
+            error!("Request ID {}: Batch generation aborted: {}", request_id, e);
+            HttpResponse::InternalServerError().body("Image generation failed")
+        }
+    }
+}
+
+// アイコンを格子状に並べたシートを返すエンドポイント
+// 拡張子が画像の形式ならシートの画像を、json ならスプライトマップを、css ならスタイルシートを返す
+// 同じクエリパラメータで呼べば、スプライトマップの位置は画像と一致する
+async fn sprite_sheet(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<IconQuery>,
+    sheet: web::Query<sprite::SheetQuery>,
+) -> impl Responder {
+    let request_id = request_id(&req);
+    let extension = path.into_inner();
+    debug!("Request ID {}: Sprite sheet generation started", request_id);
+
+    let options = match icon_options(&query) {
+        Ok(options) => options,
+        Err(message) => return bad_request(request_id, message),
+    };
+    let size = match query.size {
+        Some(_) => options.size,
+        None => sprite::DEFAULT_SPRITE_SIZE,
+    };
+    // count で seed がなければスプライトマップの識別子は毎回変わるので、
+    // ここで seed を決めて画像の URL に加え、画像にも同じ識別子が並ぶようにする
+    let map = matches!(extension.as_str(), "json" | "css");
+    let chosen_seed =
+        (map && sheet.count.is_some() && query.seed.is_none()).then(|| Uuid::new_v4().to_string());
+    let mut rng = seeded_rng(query.seed.as_deref().or(chosen_seed.as_deref()));
+    let layout = match sprite::SheetLayout::new(&sheet, size, &mut rng) {
+        Ok(layout) => layout,
+        Err(message) => return bad_request(request_id, message),
+    };
+
+    // スプライトマップからは同じパラメータで描画した PNG のシートを参照する
+    let query_string = match (req.query_string(), &chosen_seed) {
+        (query_string, None) => query_string.to_string(),
+        ("", Some(seed)) => format!("seed={}", seed),
+        (query_string, Some(seed)) => format!("{}&seed={}", query_string, seed),
+    };
+    let image = match query_string.as_str() {
+        "" => "sprites.png".to_string(),
+        query_string => format!("sprites.png?{}", query_string),
+    };
+    match extension.as_str() {
+        "json" => return HttpResponse::Ok().json(sprite::sprite_map(&layout, &image)),
+        "css" => {
+            return HttpResponse::Ok()
+                .content_type("text/css")
+                .body(sprite::sprite_css(&layout, &image))
+        }
+        _ => (),
+    }
+    // SVG と ICO のシートは作らない
+    let format = match OutputFormat::from_name(&extension) {
+        Some(OutputFormat::Svg | OutputFormat::Json | OutputFormat::Ico) | None => {
+            return HttpResponse::NotFound().finish()
+        }
+        Some(format) => format,
+    };
+
+    // 生成に時間がかかるのでワーカーをふさがないように別のスレッドで描画する
+    let result = web::block(move || {
+        let icons: Vec<RgbaImage> = layout
+            .identifiers
+            .iter()
+            .map(|identifier| {
+                let mut rng = seeded_rng(Some(identifier));
+                let composition = compose_icon(&mut rng, &options, request_id);
+                raster::draw_icon(&composition, size, options.antialias)
+            })
+            .collect();
+        let sheet = sprite::draw_sheet(&layout, &icons);
+        encode_image(&sheet, format)
+    })
+    .await;
+    match result {
+        Ok(Ok(buffer)) => {
+            info!(
+                "Request ID {}: Sprite sheet generation completed",
+                request_id
+            );
+            HttpResponse::Ok().content_type(format.mime()).body(buffer)
+        }
+        Ok(Err(e)) => {
+            error!(
+                "Request ID {}: Failed to write image to buffer: {}",
+                request_id, e
+            );
+            HttpResponse::InternalServerError().body("Image generation failed")
+        }
+        Err(e) => {
+            error!(
+                "Request ID {}: Sprite sheet generation aborted: {}",
+                request_id, e
+            );
+            HttpResponse::InternalServerError().body("Image generation failed")
+        }
+    }
+}
+
+// パレットの一覧を返すエンドポイント
+async fn list_palettes() -> impl Responder {
+    HttpResponse::Ok().json(palette::PALETTES)
+}
+
+// 使える図形の一覧を返すエンドポイント
+// default は shapes パラメータを省略したときに選ばれる図形かどうか
+async fn list_shapes() -> impl Responder {
+    let shapes: Vec<serde_json::Value> = shape::registry()
+        .shapes()
+        .map(|shape| {
+            serde_json::json!({
+                "name": shape.name(),
+                "default": shape::DEFAULT_SHAPES.contains(&shape.name()),
+            })
+        })
+        .collect();
+    HttpResponse::Ok().json(shapes)
+}
+
+// リクエストIDを取得する関数
+fn request_id(req: &HttpRequest) -> Uuid {
+    req.extensions()
+        .get::<Uuid>()
+        .cloned()
+        .unwrap_or_else(Uuid::new_v4)
+}
+
+// 不正なパラメータに対するレスポンスを作る関数
+fn bad_request(request_id: Uuid, message: String) -> HttpResponse {
+    warn!("Request ID {}: Bad request: {}", request_id, message);
+    HttpResponse::BadRequest().body(message)
+}
+
+// 応えられる出力形式がないときのレスポンスを作る関数
+fn not_acceptable(request_id: Uuid) -> HttpResponse {
+    warn!("Request ID {}: No acceptable format", request_id);
+    let formats = ["png", "svg", "json", "jpeg", "webp", "gif", "bmp", "ico"];
+    HttpResponse::NotAcceptable().body(format!(
+        "none of the requested media types is available, supported formats: {}",
+        formats.join(", ")
+    ))
+}
+
+// アイコンの構図
+// background が None なら背景は透明
+// symmetry があれば shapes は対称の元になる部分だけで、残りは描画するときに写して描く
+// format=json ではこの構図をそのままレシピとして返し、/render ではレシピからこの構図を読み込む
+#[derive(Clone, Serialize, Deserialize)]
+struct Composition {
+    #[serde(default, with = "color::hex_option")]
+    background: Option<Rgb<u8>>,
+    // 背景色から始まるグラデーション
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    background_gradient: Option<Gradient>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    symmetry: Option<Symmetry>,
+    // 切り抜く形の外側は透明になる
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mask: Option<Mask>,
+    shapes: Vec<ShapeSpec>,
+    // 図形の上に重ねる文字列
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    overlay: Option<TextOverlay>,
+}
+
+// 図形の輪郭の線
+// 線は輪郭を中心に描き、角は丸める
+#[derive(Clone, Serialize, Deserialize)]
+struct Stroke {
+    width: f32,
+    // None なら図形の塗りと同じ色 (グラデーションも含む) で描く
+    #[serde(
+        default,
+        with = "color::hex_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    color: Option<Rgb<u8>>,
+    // true なら図形を塗らずに線だけを描く
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    line_art: bool,
+}
+
+// アイコンの中央に重ねる文字列
+#[derive(Clone, Serialize, Deserialize)]
+struct TextOverlay {
+    text: String,
+    #[serde(with = "color::hex")]
+    color: Rgb<u8>,
+}
+
+// 構図に含まれる図形
+// 位置と大きさは ICON_SIZE 四方を基準にした値で、描画するときに出力サイズに合わせて拡大縮小する
+#[derive(Clone, Serialize, Deserialize)]
+struct ShapeSpec {
+    #[serde(
+        rename = "type",
+        serialize_with = "serialize_shape_type",
+        deserialize_with = "deserialize_shape_type"
+    )]
+    shape_type: &'static dyn Shape,
+    #[serde(rename = "center")]
+    position: (f32, f32),
+    size: u32,
+    angle: f32,
+    #[serde(with = "color::hex")]
+    color: Rgb<u8>,
+    #[serde(default = "default_opacity")]
+    opacity: f32,
+    // color から始まるグラデーション
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gradient: Option<Gradient>,
+    // 塗りの上に描く模様
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pattern: Option<Pattern>,
+    // 輪郭の線
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stroke: Option<Stroke>,
+    // true なら中心を通る縦の直線で左右を裏返して描く
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    mirrored: bool,
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+// 図形の種類を名前で書き出す関数
+fn serialize_shape_type<S: Serializer>(
+    shape_type: &&'static dyn Shape,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(shape_type.name())
+}
+
+// 名前から図形の種類を読み込む関数
+fn deserialize_shape_type<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<&'static dyn Shape, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    let registry = shape::registry();
+    registry.find(&name).ok_or_else(|| {
+        let names: Vec<&str> = registry.shapes().map(|shape| shape.name()).collect();
+        de::Error::custom(format!(
+            "unknown shape type `{}`, expected one of {}",
+            name,
+            names.join(", ")
+        ))
+    })
+}
+
+// レシピの値が描画できる範囲にあるか確かめる関数
+fn validate_recipe(recipe: &Composition) -> Result<(), String> {
+    if recipe.shapes.len() > MAX_RECIPE_SHAPES {
+        return Err(format!(
+            "a recipe can contain at most {} shapes: {}",
+            MAX_RECIPE_SHAPES,
+            recipe.shapes.len()
+        ));
+    }
+    if recipe
+        .background_gradient
+        .is_some_and(|gradient| !gradient.is_finite())
+    {
+        return Err("background_gradient: angle must be finite".to_string());
+    }
+    if let Some(overlay) = &recipe.overlay {
+        text::check_overlay(&overlay.text).map_err(|e| format!("overlay: {}", e))?;
+    }
+    for (i, shape) in recipe.shapes.iter().enumerate() {
+        if shape.size > MAX_RECIPE_SHAPE_SIZE {
+            return Err(format!(
+                "shapes[{}]: size must be at most {}: {}",
+                i, MAX_RECIPE_SHAPE_SIZE, shape.size
+            ));
+        }
+        if !(shape.position.0.is_finite() && shape.position.1.is_finite()) {
+            return Err(format!("shapes[{}]: center must be finite", i));
+        }
+        if !shape.angle.is_finite() {
+            return Err(format!("shapes[{}]: angle must be finite", i));
+        }
+        if shape.gradient.is_some_and(|gradient| !gradient.is_finite()) {
+            return Err(format!("shapes[{}]: gradient angle must be finite", i));
+        }
+        if let Some(pattern) = &shape.pattern {
+            if !(MIN_PATTERN_SPACING..=MAX_PATTERN_SPACING).contains(&pattern.spacing) {
+                return Err(format!(
+                    "shapes[{}]: pattern spacing must be between {} and {}: {}",
+                    i, MIN_PATTERN_SPACING, MAX_PATTERN_SPACING, pattern.spacing
+                ));
+            }
+        }
+        if let Some(stroke) = &shape.stroke {
+            if !(MIN_STROKE_WIDTH..=MAX_STROKE_WIDTH).contains(&stroke.width) {
+                return Err(format!(
+                    "shapes[{}]: stroke width must be between {} and {}: {}",
+                    i, MIN_STROKE_WIDTH, MAX_STROKE_WIDTH, stroke.width
+                ));
+            }
+        }
+        if !(0.0..=1.0).contains(&shape.opacity) {
+            return Err(format!(
+                "shapes[{}]: opacity must be between 0.0 and 1.0: {}",
+                i, shape.opacity
+            ));
+        }
+    }
+    Ok(())
+}
+
+// 1つのアイコンを描画する画像の一辺のピクセル数とコマ数を返す関数
+// SVG と JSON は描画しないので None を返す
+fn raster_work(options: &IconOptions, format: OutputFormat) -> Option<(u32, u32)> {
+    let size = match format {
+        OutputFormat::Svg | OutputFormat::Json => return None,
+        OutputFormat::Ico => options.size.min(MAX_ICO_SIZE),
+        _ => options.size,
+    };
+    let frames = options
+        .animation
+        .as_ref()
+        .map_or(1, |animation| animation.frames);
+    Some((size, frames))
+}
+
+// レシピを画像に描画するのにかかる手間が上限を超えないか確かめる関数
+// 図形はそれぞれ収まる円の外接矩形のうち画像の内側にある画素を塗るので、その数を合計する
+fn check_render_cost(
+    recipe: &Composition,
+    options: &IconOptions,
+    format: OutputFormat,
+) -> Result<(), String> {
+    let Some((size, frames)) = raster_work(options, format) else {
+        return Ok(());
+    };
+    let area: f32 = recipe
+        .drawn_shapes()
+        .iter()
+        .map(|shape| {
+            let stroke = shape.stroke.as_ref();
+            let radius = shape.radius() + stroke.map_or(0.0, |stroke| stroke.width / 2.0);
+            let extent = |center: f32| {
+                ((center + radius).min(ICON_SIZE as f32) - (center - radius).max(0.0)).max(0.0)
+            };
+            // 塗り、模様、線はそれぞれ別に塗る
+            let passes = 1
+                + shape.pattern.is_some() as u32
+                + stroke.is_some_and(|stroke| !stroke.line_art) as u32;
+            extent(shape.position.0) * extent(shape.position.1) * passes as f32
+        })
+        .sum();
+    let pixels = area / (ICON_SIZE * ICON_SIZE) as f32 * (size * size) as f32 * frames as f32;
+    if pixels > MAX_RECIPE_PIXELS {
+        return Err(format!(
+            "the recipe is too large to render at {} pixels{}: use fewer or smaller shapes or a smaller size{}",
+            size,
+            if frames > 1 { " with animation" } else { "" },
+            if frames > 1 { " or fewer frames" } else { "" }
+        ));
+    }
+    Ok(())
+}
+
+impl Composition {
+    // 対称に写した図形も含めて、描く順に図形を並べる関数
+    // 写した図形は元の図形のすぐ後に描くので、重なり方も対称になる
+    fn drawn_shapes(&self) -> Vec<ShapeSpec> {
+        match self.symmetry {
+            None => self.shapes.clone(),
+            Some(symmetry) => self
+                .shapes
+                .iter()
+                .flat_map(|shape| symmetry.images(shape))
+                .collect(),
+        }
+    }
+}
+
+impl ShapeSpec {
+    // 図形がすっぽり収まる円の半径
+    fn radius(&self) -> f32 {
+        self.shape_type.radius(self.size as f32)
+    }
+
+    // scale 倍に拡大した描画先の座標で図形の輪郭を求める関数
+    fn geometry(&self, scale: f32) -> Geometry {
+        let center = (self.position.0 * scale, self.position.1 * scale);
+        let geometry = self
+            .shape_type
+            .geometry(center, self.size as f32 * scale, self.angle);
+        if self.mirrored {
+            geometry.mirrored(center.0)
+        } else {
+            geometry
+        }
+    }
+}
+
+// アイコンの構図を決める関数
+fn compose_icon<R: Rng>(rng: &mut R, options: &IconOptions, request_id: Uuid) -> Composition {
+    // 背景色を固定したり透明にしたりする場合も、乱数列を揃えるために背景色は選んでおく
+    let random_background = random_color(rng, &options.colors);
+    let background = options.background.unwrap_or(random_background);
+    debug!(
+        "Request ID {}: Background color selected: {}",
+        request_id,
+        color::to_hex(background)
+    );
+    let background = (!options.transparent).then_some(background);
+
+    let mut shapes = Vec::new();
+    match options.layout.placements() {
+        Some((positions, sizes)) => {
+            for (i, position) in positions.into_iter().enumerate() {
+                debug!(
+                    "Request ID {}: Drawing shape {} at {:?}",
+                    request_id,
+                    i + 1,
+                    position
+                );
+                let place = |_: &mut R, _| Some(position);
+                let shape =
+                    random_shape(rng, options, &sizes, place, background, &shapes, request_id);
+                shapes.extend(shape);
+            }
+        }
+        None => {
+            // 散らばらせる場合は図形の大きさが決まってから重ならない場所を探す
+            let Layout::Scatter(count) = options.layout else {
+                unreachable!("only scatter has no fixed placements");
+            };
+            let sizes = layout::scatter_sizes(count);
+            for i in 0..count {
+                let place = |rng: &mut R, radius| layout::scatter_position(rng, radius, &shapes);
+                let shape =
+                    random_shape(rng, options, &sizes, place, background, &shapes, request_id);
+                match shape {
+                    Some(shape) => shapes.push(shape),
+                    None => debug!(
+                        "Request ID {}: No room left for shape {}",
+                        request_id,
+                        i + 1
+                    ),
+                }
+            }
+        }
+    }
+
+    // 対称にする場合は、対称の元になる部分にある図形だけを残す
+    if let Some(symmetry) = options.symmetry {
+        shapes.retain(|shape| symmetry.contains(shape.position));
+    }
+    // 切り抜いても四隅の図形が残るように、構図全体を中心に向かって縮める
+    if options.safe_zone < 1.0 {
+        let center = ICON_SIZE as f32 / 2.0;
+        for shape in &mut shapes {
+            shape.position = (
+                center + (shape.position.0 - center) * options.safe_zone,
+                center + (shape.position.1 - center) * options.safe_zone,
+            );
+            shape.size = ((shape.size as f32 * options.safe_zone).round() as u32).max(1);
+        }
+    }
+
+    // 図形を決めてから塗りを選ぶので、グラデーションや模様にしても図形は単色のときと変わらない
+    let mut background_gradient = None;
+    if let Some((style, target)) = options.gradient {
+        if target.background() {
+            background_gradient = background.map(|color| style.random(rng, &options.colors, color));
+        }
+        if target.shapes() {
+            for shape in &mut shapes {
+                shape.gradient = Some(style.random(rng, &options.colors, shape.color));
+            }
+        }
+    }
+
+    if let Some((style, spacing)) = options.pattern {
+        for shape in &mut shapes {
+            shape.pattern = Some(style.random(rng, &options.colors, shape.color, spacing));
+        }
+    }
+
+    if let Some(width) = options.stroke_width {
+        // 塗った図形の線は、重なった図形の境目が見えるように背景色で描く
+        let color = match options.stroke_color {
+            Some(color) => Some(color),
+            None if options.line_art => None,
+            None => Some(background.unwrap_or(Rgb([0, 0, 0]))),
+        };
+        for shape in &mut shapes {
+            shape.stroke = Some(Stroke {
+                width,
+                color,
+                line_art: options.line_art,
+            });
+        }
+    }
+
+    let mut composition = Composition {
+        background,
+        background_gradient,
+        symmetry: options.symmetry,
+        mask: options.mask,
+        shapes,
+        overlay: None,
+    };
+    // 文字の色は、文字を重ねる前の構図を描いて文字の下の色から選ぶ
+    if let Some(text) = &options.text {
+        let color = options.text_color.unwrap_or_else(|| {
+            let img = raster::draw_icon(&composition, OVERLAY_SAMPLE_SIZE, true);
+            let glyphs = text::layout_overlay(text, OVERLAY_SAMPLE_SIZE as f32);
+            text::contrasting_text_color(&img, &glyphs)
+        });
+        debug!(
+            "Request ID {}: Overlay text color selected: {}",
+            request_id,
+            color::to_hex(color)
+        );
+        composition.overlay = Some(TextOverlay {
+            text: text.clone(),
+            color,
+        });
+    }
+    composition
+}
+
+// 出力形式に合わせてレスポンスを作る関数
+// 大きな画像やアニメーションは描画に時間がかかるので、ワーカーをふさがないように別のスレッドで描画する
+async fn icon_response(
+    composition: Composition,
+    options: IconOptions,
+    format: OutputFormat,
+    request_id: Uuid,
+) -> HttpResponse {
+    if let Err(message) = check_animation(&options, format) {
+        return bad_request(request_id, message);
+    }
+    let result =
+        web::block(move || render_icon(&composition, &options, format).map_err(|e| e.to_string()))
+            .await;
+    match result {
+        Ok(Ok(body)) => {
+            info!("Request ID {}: Icon generation completed", request_id);
+            HttpResponse::Ok().content_type(format.mime()).body(body)
+        }
+        Ok(Err(e)) => {
+            error!(
+                "Request ID {}: Failed to write image to buffer: {}",
+                request_id, e
+            );
+            HttpResponse::InternalServerError().body("Image generation failed")
+        }
+        Err(e) => {
+            error!("Request ID {}: Icon generation aborted: {}", request_id, e);
+            HttpResponse::InternalServerError().body("Image generation failed")
+        }
+    }
+}
+
+// 構図を指定された形式で書き出す関数
+fn render_icon(
+    composition: &Composition,
+    options: &IconOptions,
+    format: OutputFormat,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if let Some(animation) = &options.animation {
+        match format {
+            OutputFormat::Gif => {
+                return animation.encode_gif(composition, options.size, options.antialias)
+            }
+            OutputFormat::Png => {
+                return animation.encode_apng(composition, options.size, options.antialias)
+            }
+            _ => (),
+        }
+    }
+    match format {
+        OutputFormat::Svg => Ok(svg::render_svg(composition, options.size).into_bytes()),
+        OutputFormat::Json => Ok(serde_json::to_vec(composition)?),
+        _ => {
+            // ICO は 256 ピクセルまでしか書き出せないので、それより大きければ縮めて描画する
+            let size = if format == OutputFormat::Ico {
+                options.size.min(MAX_ICO_SIZE)
+            } else {
+                options.size
+            };
+            let img = raster::draw_icon(composition, size, options.antialias);
+            Ok(encode_image(&img, format)?)
+        }
+    }
+}
+
+// 画像を指定された形式で書き出す関数
+fn encode_image(img: &RgbaImage, format: OutputFormat) -> image::ImageResult<Vec<u8>> {
+    let mut buffer = Vec::new();
+    let mut cursor = Cursor::new(&mut buffer);
+    match format {
+        // JPEG は透明度を持てないので白い背景に重ねる
+        OutputFormat::Jpeg => {
+            flatten(img, Rgb([255, 255, 255])).write_to(&mut cursor, ImageOutputFormat::Jpeg(90))?
+        }
+        OutputFormat::Webp => img.write_to(&mut cursor, ImageOutputFormat::WebP)?,
+        OutputFormat::Gif => img.write_to(&mut cursor, ImageOutputFormat::Gif)?,
+        OutputFormat::Bmp => img.write_to(&mut cursor, ImageOutputFormat::Bmp)?,
+        OutputFormat::Ico => img.write_to(&mut cursor, ImageOutputFormat::Ico)?,
+        _ => img.write_to(&mut cursor, ImageOutputFormat::Png)?,
+    }
+    Ok(buffer)
+}
+
+// 画像を不透明な背景色に重ねる関数
+fn flatten(img: &RgbaImage, background: Rgb<u8>) -> RgbImage {
+    RgbImage::from_fn(img.width(), img.height(), |x, y| {
+        let pixel = img.get_pixel(x, y);
+        let alpha = pixel[3] as f32 / 255.0;
+        Rgb([0, 1, 2].map(|i| {
+            (pixel[i] as f32 * alpha + background[i] as f32 * (1.0 - alpha)).round() as u8
+        }))
+    })
+}
+
+// HTTPサーバーを起動する関数
+// 独自の図形を使うなら、先に shape::install で図形の一覧を設定しておく
+pub async fn serve() -> std::io::Result<()> {
+    HttpServer::new(|| {
+        App::new()
+            .wrap(Logger::default())
+            .wrap(RequestId)
+            .app_data(
+                web::JsonConfig::default()
+                    .limit(MAX_JSON_BODY_SIZE)
+                    .error_handler(|err, _| {
+                        // 壊れたレシピには理由を添えて 400 を返す
+                        let message = err.to_string();
+                        InternalError::from_response(err, HttpResponse::BadRequest().body(message))
+                            .into()
+                    }),
+            )
+            .route("/generate-icon", web::get().to(generate_icon))
+            .route("/icons/{identifier}.{extension}", web::get().to(identicon))
+            .route("/favicons/{identifier}.zip", web::get().to(favicon_bundle))
+            .route("/batch", web::post().to(generate_batch))
+            .route("/sprites.{extension}", web::get().to(sprite_sheet))
+            .route("/palettes", web::get().to(list_palettes))
+            .route("/render", web::post().to(render_recipe))
+            .route("/shapes", web::get().to(list_shapes))
+    })
+    .bind("0.0.0.0:8080")?
+    .run()
+    .await
+}
+
+// シードから乱数生成器を作る関数
+// ChaCha8Rng はプラットフォームに依存しない乱数列を返すので、同じシードなら常に同じアイコンになる
+fn seeded_rng(seed: Option<&str>) -> ChaCha8Rng {
+    match seed {
+        Some(seed) => ChaCha8Rng::from_seed(Sha256::digest(seed.as_bytes()).into()),
+        None => ChaCha8Rng::from_entropy(),
+    }
+}
+
+// ランダムな形を選ぶ関数
+// 種類と大きさを選んでから、place に収まる円の半径を渡して置く場所を決める
+// 置く場所がなければ None を返す
+// contrast が指定されていれば、背景と先に置いた図形のうち重なるものから色が離れるように選ぶ
+fn random_shape<R: Rng>(
+    rng: &mut R,
+    options: &IconOptions,
+    sizes: &ShapeSizes,
+    place: impl FnOnce(&mut R, f32) -> Option<(f32, f32)>,
+    background: Option<Rgb<u8>>,
+    placed: &[ShapeSpec],
+    request_id: Uuid,
+) -> Option<ShapeSpec> {
+    let shape_type = options.shapes[rng.gen_range(0..options.shapes.len() as u8) as usize];
+    let size = match sizes {
+        ShapeSizes::Size(range) => rng.gen_range(range.clone()),
+        ShapeSizes::Radius(range) => {
+            let radius = rng.gen_range(range.clone());
+            ((radius / shape_type.radius(1.0)).round() as u32).clamp(1, MAX_RECIPE_SHAPE_SIZE)
+        }
+    };
+    let position = place(rng, shape_type.radius(size as f32))?;
+    let angle = rng.gen_range(0.0..2.0 * PI);
+    let color = match options.contrast {
+        Some(min_delta_e) => {
+            let radius = shape_type.radius(size as f32);
+            let neighbors = placed.iter().filter(|shape| {
+                let (dx, dy) = (shape.position.0 - position.0, shape.position.1 - position.1);
+                dx.hypot(dy) < shape.radius() + radius
+            });
+            let avoid: Vec<Rgb<u8>> = background
+                .into_iter()
+                .chain(neighbors.map(|shape| shape.color))
+                .collect();
+            contrasting_color(rng, &options.colors, &avoid, min_delta_e)
+        }
+        None => random_color(rng, &options.colors),
+    };
+
+    debug!(
+        "Request ID {}: Drawing shape type: {}, size: {}, angle: {}, color: {}",
+        request_id,
+        shape_type.name(),
+        size,
+        angle,
+        color::to_hex(color)
+    );
+
+    Some(ShapeSpec {
+        shape_type,
+        position,
+        size,
+        angle,
+        color,
+        opacity: options.opacity,
+        gradient: None,
+        pattern: None,
+        stroke: None,
+        mirrored: false,
+    })
+}
+
+// 避けたい色のすべてと min_delta_e 以上離れた色をランダムに選ぶ関数
+// 条件を満たす色がなければ、避けたい色から最も離れた色を選ぶ
+fn contrasting_color<R: Rng>(
+    rng: &mut R,
+    colors: &[Rgb<u8>],
+    avoid: &[Rgb<u8>],
+    min_delta_e: f32,
+) -> Rgb<u8> {
+    let nearest = |color: Rgb<u8>| {
+        avoid
+            .iter()
+            .map(|&other| color::delta_e(color, other))
+            .fold(f32::MAX, f32::min)
+    };
+    let candidates: Vec<Rgb<u8>> = colors
+        .iter()
+        .copied()
+        .filter(|&color| nearest(color) >= min_delta_e)
+        .collect();
+
+    if candidates.is_empty() {
+        colors
+            .iter()
+            .copied()
+            .max_by(|&a, &b| nearest(a).total_cmp(&nearest(b)))
+            .expect("colors must not be empty")
+    } else {
+        random_color(rng, &candidates)
+    }
+}
+
+// パレットの color 以外の色をランダムに選ぶ関数
+// パレットに他の色がなければ color を返す
+fn other_color<R: Rng>(rng: &mut R, colors: &[Rgb<u8>], color: Rgb<u8>) -> Rgb<u8> {
+    let others: Vec<Rgb<u8>> = colors.iter().copied().filter(|&c| c != color).collect();
+    if others.is_empty() {
+        color
+    } else {
+        random_color(rng, &others)
+    }
+}
+
+// ランダムな色を選択する関数
+fn random_color<R: Rng>(rng: &mut R, colors: &[Rgb<u8>]) -> Rgb<u8> {
+    // usize はプラットフォームによって乱数列が変わるので u32 で選ぶ
+    let color_index = rng.gen_range(0..colors.len() as u32) as usize;
+    colors[color_index]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // クエリ文字列からアイコンを生成して指定した形式で書き出す関数
+    fn generate(query: &str, format: OutputFormat) -> Vec<u8> {
+        let query = web::Query::<IconQuery>::from_query(query).unwrap();
+        let options = icon_options(&query).unwrap();
+        let mut rng = seeded_rng(query.seed.as_deref());
+        let composition = compose_icon(&mut rng, &options, Uuid::nil());
+        render_icon(&composition, &options, format).unwrap()
+    }
+
+    // Accept ヘッダーから出力形式を選ぶ関数
+    fn accept(value: &str) -> Option<OutputFormat> {
+        let req = actix_web::test::TestRequest::default()
+            .insert_header((header::ACCEPT, value))
+            .to_http_request();
+        accept_format(&req)
+    }
+
+    #[test]
+    fn accept_format_prefers_what_browsers_list_for_images() {
+        // Chrome と Firefox が img 要素の読み込みで送るヘッダー
+        // 明示された image/webp が image/* と同じ重みで先に書かれているので WebP を選ぶ
+        let chrome = "image/avif,image/webp,image/apng,image/svg+xml,image/*,*/*;q=0.8";
+        let firefox = "image/avif,image/webp,image/png,image/svg+xml,image/*;q=0.8,*/*;q=0.5";
+        assert_eq!(accept(chrome), Some(OutputFormat::Webp));
+        assert_eq!(accept(firefox), Some(OutputFormat::Webp));
+    }
+
+    #[test]
+    fn accept_format_skips_refused_formats_for_wildcards() {
+        assert_eq!(accept("image/png;q=0, image/*"), Some(OutputFormat::Webp));
+        assert_eq!(accept("*/*;q=0"), None);
+        assert_eq!(accept("image/png;q=0"), None);
+    }
+
+    #[test]
+    fn accept_format_picks_the_first_of_equal_weights() {
+        assert_eq!(accept("image/gif, image/bmp"), Some(OutputFormat::Gif));
+        assert_eq!(accept("image/bmp, image/gif"), Some(OutputFormat::Bmp));
+        assert_eq!(
+            accept("image/bmp;q=0.5, image/gif;q=0.9"),
+            Some(OutputFormat::Gif)
+        );
+    }
+
+    #[test]
+    fn accept_format_reads_application_wildcards_and_spaced_weights() {
+        assert_eq!(accept("application/*"), Some(OutputFormat::Json));
+        assert_eq!(
+            accept("application/*, image/*;q=0.5"),
+            Some(OutputFormat::Json)
+        );
+        assert_eq!(accept("application/json;q=0, application/*"), None);
+        assert_eq!(
+            accept("image/gif; q= 0.5, image/bmp"),
+            Some(OutputFormat::Bmp)
+        );
+        assert_eq!(
+            accept("image/gif;Q = 0.5, image/bmp"),
+            Some(OutputFormat::Bmp)
+        );
+        assert_eq!(accept("image/gif; q = 0, image/*"), Some(OutputFormat::Png));
+    }
+
+    #[test]
+    fn accept_format_defaults_to_png() {
+        let req = actix_web::test::TestRequest::default().to_http_request();
+        assert_eq!(accept_format(&req), Some(OutputFormat::Png));
+        assert_eq!(accept(""), Some(OutputFormat::Png));
+        assert_eq!(accept("text/html"), None);
+    }
+
+    #[test]
+    fn same_seed_gives_identical_png_bytes() {
+        let first = generate("seed=alice", OutputFormat::Png);
+        assert_eq!(first, generate("seed=alice", OutputFormat::Png));
+        assert_ne!(first, generate("seed=bob", OutputFormat::Png));
+    }
+
+    #[test]
+    fn seed_gives_the_same_composition_on_every_platform() {
+        // 乱数列がプラットフォームや再起動で変わらないことを、決まった構図と比べて確かめる
+        let recipe = generate("seed=alice", OutputFormat::Json);
+        assert_eq!(
+            String::from_utf8(recipe).unwrap(),
+            concat!(
+                r##"{"background":"#38b48b","shapes":["##,
+                r##"{"type":"semi_circle","center":[0.0,0.0],"size":224,"angle":0.53267634,"color":"#38b48b","opacity":1.0},"##,
+                r##"{"type":"square","center":[499.0,0.0],"size":214,"angle":0.22458139,"color":"#ee7800","opacity":1.0},"##,
+                r##"{"type":"hexagon","center":[0.0,499.0],"size":162,"angle":1.6579748,"color":"#e4dc8a","opacity":1.0},"##,
+                r##"{"type":"semi_circle","center":[499.0,499.0],"size":237,"angle":2.039209,"color":"#b7282e","opacity":1.0},"##,
+                r##"{"type":"pentagon","center":[250.0,250.0],"size":218,"angle":3.0203497,"color":"#f3f3f3","opacity":1.0}]}"##
+            )
+        );
+    }
+}
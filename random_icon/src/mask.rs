@@ -0,0 +1,112 @@
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::fmt::Write;
+
+// 角丸四角形の角の半径 (一辺に対する割合)
+const CORNER_RADIUS: f32 = 0.2;
+// スクワークル (超楕円 |x|^n + |y|^n = 1) の次数
+const SQUIRCLE_EXPONENT: f32 = 4.0;
+// SVG でスクワークルを近似する多角形の頂点の数
+const SQUIRCLE_POINTS: u32 = 64;
+
+// アイコンを切り抜く形
+// 外側は透明になる
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mask {
+    Circle,
+    // 角丸四角形
+    Rounded,
+    // 角丸四角形より滑らかに角を丸めた超楕円
+    Squircle,
+}
+
+impl Mask {
+    // 名前から切り抜く形を選ぶ関数
+    pub fn from_name(name: &str) -> Option<Mask> {
+        match name {
+            "circle" => Some(Mask::Circle),
+            "rounded" => Some(Mask::Rounded),
+            "squircle" => Some(Mask::Squircle),
+            _ => None,
+        }
+    }
+
+    // safe_zone を省略したときに構図を縮める割合
+    // 四隅に置いた図形の中心が切り抜いた後も内側に残るようにする
+    pub fn default_safe_zone(self) -> f32 {
+        match self {
+            Mask::Circle => 0.6,
+            Mask::Rounded => 0.8,
+            Mask::Squircle => 0.7,
+        }
+    }
+
+    // 一辺が size の正方形に収まる切り抜く形の輪郭までの符号付き距離を求める関数 (内側が負)
+    fn distance(self, size: f32, x: f32, y: f32) -> f32 {
+        let half = size / 2.0;
+        let (dx, dy) = ((x - half).abs(), (y - half).abs());
+        match self {
+            Mask::Circle => dx.hypot(dy) - half,
+            Mask::Rounded => {
+                let radius = size * CORNER_RADIUS;
+                let (qx, qy) = (dx - (half - radius), dy - (half - radius));
+                qx.max(0.0).hypot(qy.max(0.0)) + qx.max(qy).min(0.0) - radius
+            }
+            Mask::Squircle => {
+                // 超楕円の式の値を勾配の大きさで割って距離に近づける
+                let (u, v) = (dx / half, dy / half);
+                let n = SQUIRCLE_EXPONENT;
+                let value = (u.powf(n) + v.powf(n)).powf(1.0 / n);
+                if value <= 0.0 {
+                    return -half;
+                }
+                let gradient = (u / value).powf(n - 1.0).hypot((v / value).powf(n - 1.0));
+                (value - 1.0) * half / gradient
+            }
+        }
+    }
+
+    // 画像の切り抜く形の外側を透明にする関数
+    // antialias が false なら輪郭の内側に中心がある画素だけを残す
+    pub fn apply(self, img: &mut RgbaImage, antialias: bool) {
+        let size = img.width() as f32;
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            let d = self.distance(size, x as f32 + 0.5, y as f32 + 0.5);
+            let coverage = if antialias {
+                (0.5 - d).clamp(0.0, 1.0)
+            } else if d <= 0.0 {
+                1.0
+            } else {
+                0.0
+            };
+            pixel[3] = (pixel[3] as f32 * coverage).round() as u8;
+        }
+    }
+
+    // 一辺が size の正方形に収まる切り抜く形をSVGの要素に変換する関数
+    pub fn svg_element(self, size: u32) -> String {
+        let half = size as f32 / 2.0;
+        match self {
+            Mask::Circle => format!(r#"<circle cx="{0}" cy="{0}" r="{0}"/>"#, half),
+            Mask::Rounded => format!(
+                r#"<rect width="{0}" height="{0}" rx="{1}"/>"#,
+                size,
+                size as f32 * CORNER_RADIUS
+            ),
+            Mask::Squircle => {
+                let mut points = String::new();
+                for i in 0..SQUIRCLE_POINTS {
+                    let theta = 2.0 * PI * i as f32 / SQUIRCLE_POINTS as f32;
+                    let (sin, cos) = theta.sin_cos();
+                    // 超楕円の媒介変数表示
+                    let x = half + half * cos.signum() * cos.abs().powf(2.0 / SQUIRCLE_EXPONENT);
+                    let y = half + half * sin.signum() * sin.abs().powf(2.0 / SQUIRCLE_EXPONENT);
+                    let _ = write!(points, "{}{:.1},{:.1}", if i == 0 { "" } else { " " }, x, y);
+                }
+                format!(r#"<polygon points="{}"/>"#, points)
+            }
+        }
+    }
+}
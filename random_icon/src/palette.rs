@@ -0,0 +1,58 @@
+use serde::Serialize;
+
+// 色のパレット
+#[derive(Serialize)]
+pub struct Palette {
+    pub name: &'static str,
+    pub colors: &'static [&'static str],
+}
+
+// palette パラメータを省略したときのパレット
+pub const DEFAULT_PALETTE: &str = "japanese";
+
+// 組み込みのパレット
+pub const PALETTES: &[Palette] = &[
+    // 日本の伝統色
+    Palette {
+        name: "japanese",
+        colors: &[
+            "#f19072", "#e4dc8a", "#f8f4e6", "#b7282e", "#f09199", "#fef4f4", "#c39143", "#8a3b00",
+            "#f08300", "#ed6d3d", "#ee7800", "#eb6101", "#ffd900", "#ffec47", "#f8b500", "#e6b422",
+            "#2f5d50", "#007b43", "#7ebeab", "#98d98e", "#dccb18", "#928c36", "#38b48b", "#bce2e8",
+            "#a0d8ef", "#4c6cb3", "#0d0015", "#bbbcde", "#595857", "#f3f3f3", "#9d5b8b",
+        ],
+    },
+    Palette {
+        name: "pastel",
+        colors: &[
+            "#ffb3ba", "#ffdfba", "#ffffba", "#baffc9", "#bae1ff", "#e0bbe4", "#957dad", "#d291bc",
+            "#fec8d8", "#ffdfd3", "#c7ceea", "#b5ead7", "#e2f0cb", "#ffdac1", "#ff9aa2", "#a0e7e5",
+        ],
+    },
+    Palette {
+        name: "vivid",
+        colors: &[
+            "#e6194b", "#3cb44b", "#ffe119", "#4363d8", "#f58231", "#911eb4", "#46f0f0", "#f032e6",
+            "#bcf60c", "#008080", "#9a6324", "#800000", "#000075", "#ff1493", "#00bfff", "#ffd700",
+        ],
+    },
+    Palette {
+        name: "monochrome",
+        colors: &[
+            "#000000", "#1a1a1a", "#333333", "#4d4d4d", "#666666", "#808080", "#999999", "#b3b3b3",
+            "#cccccc", "#e6e6e6", "#ffffff",
+        ],
+    },
+    Palette {
+        name: "brand",
+        colors: &[
+            "#0b3d91", "#1e88e5", "#64b5f6", "#00acc1", "#26a69a", "#ffb300", "#f4511e", "#eceff1",
+            "#263238",
+        ],
+    },
+];
+
+// 名前からパレットを探す関数
+pub fn find_palette(name: &str) -> Option<&'static Palette> {
+    PALETTES.iter().find(|palette| palette.name == name)
+}
@@ -0,0 +1,429 @@
+use std::f32::consts::PI;
+
+// 曲線を近似する折れ線の分割数
+const CURVE_SEGMENTS: u32 = 16;
+
+// SVG のパスデータを多角形の一覧に変換する関数
+// 曲線と円弧は折れ線に近似し、閉じていないサブパスも閉じた多角形として扱う
+pub fn parse_path(data: &str) -> Result<Vec<Vec<(f32, f32)>>, String> {
+    let mut tokens = Tokens {
+        data: data.as_bytes(),
+        pos: 0,
+    };
+    let mut path = PathBuilder::default();
+    let mut command = None;
+
+    loop {
+        tokens.skip_separators();
+        if tokens.at_end() {
+            break;
+        }
+        // コマンドの文字を省略すると直前のコマンドを繰り返す
+        if let Some(c) = tokens.command() {
+            command = Some(c);
+        }
+        let Some(c) = command else {
+            return Err(format!(
+                "path data must start with a command at position {}",
+                tokens.pos
+            ));
+        };
+
+        let relative = c.is_ascii_lowercase();
+        let origin = if relative { path.current } else { (0.0, 0.0) };
+        match c.to_ascii_uppercase() {
+            b'M' => {
+                let point = tokens.point(origin)?;
+                path.move_to(point);
+                // M の後に続く座標は L として扱う
+                command = Some(if relative { b'l' } else { b'L' });
+            }
+            b'L' => {
+                let point = tokens.point(origin)?;
+                path.line_to(point);
+            }
+            b'H' => {
+                let x = tokens.number()? + origin.0;
+                path.line_to((x, path.current.1));
+            }
+            b'V' => {
+                let y = tokens.number()? + origin.1;
+                path.line_to((path.current.0, y));
+            }
+            b'C' => {
+                let control1 = tokens.point(origin)?;
+                let control2 = tokens.point(origin)?;
+                let point = tokens.point(origin)?;
+                path.cubic_to(control1, control2, point);
+            }
+            b'S' => {
+                let control1 = path.reflected_cubic_control();
+                let control2 = tokens.point(origin)?;
+                let point = tokens.point(origin)?;
+                path.cubic_to(control1, control2, point);
+            }
+            b'Q' => {
+                let control = tokens.point(origin)?;
+                let point = tokens.point(origin)?;
+                path.quadratic_to(control, point);
+            }
+            b'T' => {
+                let control = path.reflected_quadratic_control();
+                let point = tokens.point(origin)?;
+                path.quadratic_to(control, point);
+            }
+            b'A' => {
+                let rx = tokens.number()?;
+                let ry = tokens.number()?;
+                let rotation = tokens.number()?;
+                let large_arc = tokens.flag()?;
+                let sweep = tokens.flag()?;
+                let point = tokens.point(origin)?;
+                path.arc_to((rx, ry), rotation, large_arc, sweep, point);
+            }
+            b'Z' => {
+                path.close();
+                command = None;
+            }
+            _ => return Err(format!("unsupported path command: {}", c as char)),
+        }
+    }
+
+    let rings = path.finish();
+    if rings.is_empty() {
+        return Err("path data contains no closed area".to_string());
+    }
+    Ok(rings)
+}
+
+// パスデータの字句を読み出す
+struct Tokens<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Tokens<'_> {
+    fn at_end(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    // 空白とカンマを読み飛ばす関数
+    fn skip_separators(&mut self) {
+        while self.pos < self.data.len()
+            && (self.data[self.pos].is_ascii_whitespace() || self.data[self.pos] == b',')
+        {
+            self.pos += 1;
+        }
+    }
+
+    // コマンドの文字があれば読み出す関数
+    fn command(&mut self) -> Option<u8> {
+        let c = *self.data.get(self.pos)?;
+        if c.is_ascii_alphabetic() && c != b'e' && c != b'E' {
+            self.pos += 1;
+            Some(c)
+        } else {
+            None
+        }
+    }
+
+    // 数値を読み出す関数
+    // 1.5.5 や 1-2 のように区切りなしで続く数値も読み分ける
+    fn number(&mut self) -> Result<f32, String> {
+        self.skip_separators();
+        let start = self.pos;
+        let mut seen_dot = false;
+        let mut seen_exponent = false;
+        while let Some(&c) = self.data.get(self.pos) {
+            let sign_allowed = self.pos == start
+                || (seen_exponent && matches!(self.data[self.pos - 1], b'e' | b'E'));
+            match c {
+                b'0'..=b'9' => (),
+                b'+' | b'-' if sign_allowed => (),
+                b'.' if !seen_dot && !seen_exponent => seen_dot = true,
+                b'e' | b'E' if !seen_exponent && self.pos > start => seen_exponent = true,
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.data[start..self.pos])
+            .ok()
+            .and_then(|text| text.parse::<f32>().ok())
+            .filter(|number| number.is_finite())
+            .ok_or_else(|| format!("expected a number at position {}", start))
+    }
+
+    // 円弧のフラグを読み出す関数
+    // フラグは 1 文字なので区切りなしで続いていてもよい
+    fn flag(&mut self) -> Result<bool, String> {
+        self.skip_separators();
+        let flag = match self.data.get(self.pos) {
+            Some(b'0') => false,
+            Some(b'1') => true,
+            _ => return Err(format!("expected 0 or 1 at position {}", self.pos)),
+        };
+        self.pos += 1;
+        Ok(flag)
+    }
+
+    // 座標を読み出して origin からの位置にする関数
+    fn point(&mut self, origin: (f32, f32)) -> Result<(f32, f32), String> {
+        let x = self.number()?;
+        let y = self.number()?;
+        Ok((origin.0 + x, origin.1 + y))
+    }
+}
+
+// 読み込んだパスを多角形にまとめる
+#[derive(Default)]
+struct PathBuilder {
+    rings: Vec<Vec<(f32, f32)>>,
+    ring: Vec<(f32, f32)>,
+    current: (f32, f32),
+    // サブパスの始点
+    start: (f32, f32),
+    // S と T で反転させる直前の制御点
+    last_cubic_control: Option<(f32, f32)>,
+    last_quadratic_control: Option<(f32, f32)>,
+}
+
+impl PathBuilder {
+    fn move_to(&mut self, point: (f32, f32)) {
+        self.finish_ring();
+        self.current = point;
+        self.start = point;
+        self.last_cubic_control = None;
+        self.last_quadratic_control = None;
+    }
+
+    fn line_to(&mut self, point: (f32, f32)) {
+        self.push(point);
+        self.last_cubic_control = None;
+        self.last_quadratic_control = None;
+    }
+
+    fn cubic_to(&mut self, control1: (f32, f32), control2: (f32, f32), point: (f32, f32)) {
+        let from = self.current;
+        for i in 1..=CURVE_SEGMENTS {
+            let t = i as f32 / CURVE_SEGMENTS as f32;
+            let u = 1.0 - t;
+            let blend = |a: f32, b: f32, c: f32, d: f32| {
+                u * u * u * a + 3.0 * u * u * t * b + 3.0 * u * t * t * c + t * t * t * d
+            };
+            self.push((
+                blend(from.0, control1.0, control2.0, point.0),
+                blend(from.1, control1.1, control2.1, point.1),
+            ));
+        }
+        self.last_cubic_control = Some(control2);
+        self.last_quadratic_control = None;
+    }
+
+    fn quadratic_to(&mut self, control: (f32, f32), point: (f32, f32)) {
+        let from = self.current;
+        for i in 1..=CURVE_SEGMENTS {
+            let t = i as f32 / CURVE_SEGMENTS as f32;
+            let u = 1.0 - t;
+            let blend = |a: f32, b: f32, c: f32| u * u * a + 2.0 * u * t * b + t * t * c;
+            self.push((
+                blend(from.0, control.0, point.0),
+                blend(from.1, control.1, point.1),
+            ));
+        }
+        self.last_cubic_control = None;
+        self.last_quadratic_control = Some(control);
+    }
+
+    // 円弧を折れ線で近似する関数
+    // SVG の仕様にある端点から中心を求める変換に従う
+    fn arc_to(
+        &mut self,
+        radii: (f32, f32),
+        rotation: f32,
+        large_arc: bool,
+        sweep: bool,
+        point: (f32, f32),
+    ) {
+        let from = self.current;
+        let (mut rx, mut ry) = (radii.0.abs(), radii.1.abs());
+        if from == point {
+            return;
+        }
+        if rx == 0.0 || ry == 0.0 {
+            self.line_to(point);
+            return;
+        }
+
+        let (sin, cos) = rotation.to_radians().sin_cos();
+        let (dx, dy) = ((from.0 - point.0) / 2.0, (from.1 - point.1) / 2.0);
+        let x1 = cos * dx + sin * dy;
+        let y1 = -sin * dx + cos * dy;
+
+        // 半径が足りなければ端点に届くまで広げる
+        let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+        if lambda > 1.0 {
+            rx *= lambda.sqrt();
+            ry *= lambda.sqrt();
+        }
+
+        let numerator = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+        let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+        let sign = if large_arc == sweep { -1.0 } else { 1.0 };
+        let coefficient = sign * (numerator / denominator).max(0.0).sqrt();
+        let cx1 = coefficient * rx * y1 / ry;
+        let cy1 = -coefficient * ry * x1 / rx;
+        let cx = cos * cx1 - sin * cy1 + (from.0 + point.0) / 2.0;
+        let cy = sin * cx1 + cos * cy1 + (from.1 + point.1) / 2.0;
+
+        let angle =
+            |ux: f32, uy: f32, vx: f32, vy: f32| (ux * vy - uy * vx).atan2(ux * vx + uy * vy);
+        let (ux, uy) = ((x1 - cx1) / rx, (y1 - cy1) / ry);
+        let (vx, vy) = ((-x1 - cx1) / rx, (-y1 - cy1) / ry);
+        let start_angle = angle(1.0, 0.0, ux, uy);
+        let mut delta = angle(ux, uy, vx, vy);
+        if !sweep && delta > 0.0 {
+            delta -= 2.0 * PI;
+        } else if sweep && delta < 0.0 {
+            delta += 2.0 * PI;
+        }
+
+        for i in 1..=CURVE_SEGMENTS {
+            let theta = start_angle + delta * i as f32 / CURVE_SEGMENTS as f32;
+            let (x, y) = (rx * theta.cos(), ry * theta.sin());
+            self.push((cx + cos * x - sin * y, cy + sin * x + cos * y));
+        }
+        // 誤差を残さないように終点はそのまま使う
+        if let Some(last) = self.ring.last_mut() {
+            *last = point;
+        }
+        self.current = point;
+        self.last_cubic_control = None;
+        self.last_quadratic_control = None;
+    }
+
+    fn close(&mut self) {
+        self.finish_ring();
+        self.current = self.start;
+        self.last_cubic_control = None;
+        self.last_quadratic_control = None;
+    }
+
+    fn reflected_cubic_control(&self) -> (f32, f32) {
+        reflect(self.last_cubic_control, self.current)
+    }
+
+    fn reflected_quadratic_control(&self) -> (f32, f32) {
+        reflect(self.last_quadratic_control, self.current)
+    }
+
+    // 現在のサブパスに点を加える関数
+    // Z の後に M を挟まずに描き始めたときは、サブパスの始点から始める
+    fn push(&mut self, point: (f32, f32)) {
+        if self.ring.is_empty() {
+            self.ring.push(self.current);
+        }
+        self.ring.push(point);
+        self.current = point;
+    }
+
+    // 現在のサブパスを閉じた多角形として加える関数
+    // 面積を持たない3点未満のサブパスは捨てる
+    fn finish_ring(&mut self) {
+        let ring = std::mem::take(&mut self.ring);
+        if ring.len() >= 3 {
+            self.rings.push(ring);
+        }
+    }
+
+    fn finish(mut self) -> Vec<Vec<(f32, f32)>> {
+        self.finish_ring();
+        self.rings
+    }
+}
+
+// 直前の制御点を現在の点について反転させる関数
+// 直前が同じ種類の曲線でなければ現在の点を制御点にする
+fn reflect(control: Option<(f32, f32)>, current: (f32, f32)) -> (f32, f32) {
+    match control {
+        Some((x, y)) => (2.0 * current.0 - x, 2.0 * current.1 - y),
+        None => current,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_absolute_and_relative_lines() {
+        let rings = parse_path("M0 0 L10 0 L10 10 Z").unwrap();
+        assert_eq!(rings, vec![vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]]);
+        let rings = parse_path("m5 5 h10 v10 h-10 z").unwrap();
+        assert_eq!(
+            rings,
+            vec![vec![(5.0, 5.0), (15.0, 5.0), (15.0, 15.0), (5.0, 15.0)]]
+        );
+    }
+
+    #[test]
+    fn repeats_implicit_commands() {
+        // M の後の座標は L として、L の後の座標は L の繰り返しとして読む
+        let rings = parse_path("M0 0 10 0 10 10 0 10z").unwrap();
+        assert_eq!(
+            rings,
+            vec![vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]]
+        );
+        let rings = parse_path("m0 0 10 0 0 10z").unwrap();
+        assert_eq!(rings, vec![vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]]);
+    }
+
+    #[test]
+    fn splits_numbers_without_separators() {
+        let rings = parse_path("M0,0L1.5.5L-1-2z").unwrap();
+        assert_eq!(rings, vec![vec![(0.0, 0.0), (1.5, 0.5), (-1.0, -2.0)]]);
+        let rings = parse_path("M0 0L1e1 0L1E+1-1e1Z").unwrap();
+        assert_eq!(rings, vec![vec![(0.0, 0.0), (10.0, 0.0), (10.0, -10.0)]]);
+    }
+
+    #[test]
+    fn reads_arc_flags_without_separators() {
+        let rings = parse_path("M0 0A5 5 0 0110 0Z").unwrap();
+        let ring = &rings[0];
+        assert_eq!(ring.len(), CURVE_SEGMENTS as usize + 1);
+        assert_eq!(*ring.last().unwrap(), (10.0, 0.0));
+        // sweep が 1 なので、y が負の側を通る半円になる
+        let (x, y) = ring[CURVE_SEGMENTS as usize / 2];
+        assert!((x - 5.0).abs() < 1e-3 && (y + 5.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn approximates_curves_ending_at_the_end_point() {
+        let rings = parse_path("M0 0C0 10 10 10 10 0S20 -10 20 0Q30 10 40 0T60 0Z").unwrap();
+        let ring = &rings[0];
+        assert_eq!(ring.len(), 4 * CURVE_SEGMENTS as usize + 1);
+        assert_eq!(ring[CURVE_SEGMENTS as usize], (10.0, 0.0));
+        assert_eq!(*ring.last().unwrap(), (60.0, 0.0));
+    }
+
+    #[test]
+    fn keeps_separate_subpaths() {
+        let rings = parse_path("M0 0H10V10ZM2 2H8V8Z").unwrap();
+        assert_eq!(rings.len(), 2);
+        // Z の後に M なしで続くサブパスはサブパスの始点から始める
+        let rings = parse_path("M0 0H10V10ZL0 10 -10 10Z").unwrap();
+        assert_eq!(rings[1][0], (0.0, 0.0));
+    }
+
+    #[test]
+    fn rejects_invalid_data() {
+        assert!(parse_path("").is_err());
+        assert!(parse_path("0 0 L10 10").is_err());
+        assert!(parse_path("M0 0 L10").is_err());
+        assert!(parse_path("M0 0 X10 10").is_err());
+        assert!(parse_path("M0 0 A5 5 0 2 0 10 0").is_err());
+        assert!(parse_path("M0 0 L1e 0").is_err());
+        assert!(parse_path("M0 0 L1e39 0 L0 1 Z").is_err());
+        // 面積を持つ多角形がない
+        assert!(parse_path("M0 0 L10 10").is_err());
+    }
+}
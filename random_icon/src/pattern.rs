@@ -0,0 +1,223 @@
+use crate::color::{rotate_hue, to_hex};
+use crate::other_color;
+use image::Rgb;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::FRAC_1_SQRT_2;
+
+// 間隔に対する水玉の半径の割合
+const DOT_RADIUS: f32 = 0.25;
+// 間隔に対する網目の線の幅の割合
+const HATCH_WIDTH: f32 = 0.2;
+
+// 図形の模様の種類
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PatternKind {
+    // 図形の向きに沿った縞
+    Stripes,
+    // 水玉
+    Dots,
+    // 市松模様
+    Checker,
+    // 図形の向きから 45 度傾けた線を交差させた網目
+    Hatch,
+}
+
+const KINDS: [PatternKind; 4] = [
+    PatternKind::Stripes,
+    PatternKind::Dots,
+    PatternKind::Checker,
+    PatternKind::Hatch,
+];
+
+// 図形の模様
+// 図形の塗り (color やグラデーション) の上に color の色で模様を描く
+// 模様は図形の中心を原点にして図形と一緒に回り、裏返した図形では模様も裏返る
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pattern {
+    #[serde(rename = "type")]
+    pub kind: PatternKind,
+    #[serde(with = "crate::color::hex")]
+    pub color: Rgb<u8>,
+    // 模様の繰り返しの間隔 (ICON_SIZE 四方を基準にした値)
+    pub spacing: f32,
+}
+
+impl Pattern {
+    // 模様の色の色相を回転させる関数
+    pub fn rotate_hue(&mut self, degrees: f32) {
+        self.color = rotate_hue(self.color, degrees);
+    }
+
+    // 中心と向きを指定した図形の模様を、描画先の座標に置く関数
+    // scale は ICON_SIZE 四方から描画先への拡大率
+    pub fn frame(self, center: (f32, f32), angle: f32, mirrored: bool, scale: f32) -> PatternFrame {
+        let (sin, cos) = angle.sin_cos();
+        PatternFrame {
+            kind: self.kind,
+            spacing: self.spacing * scale,
+            center: (center.0 * scale, center.1 * scale),
+            sin,
+            cos,
+            mirrored,
+        }
+    }
+
+    // 模様をSVGの pattern 要素に変換する関数
+    // 座標は viewBox の座標で、ラスタ画像と同じく図形の中心を原点にして回す
+    pub fn svg_element(self, id: &str, center: (f32, f32), angle: f32, mirrored: bool) -> String {
+        let s = self.spacing;
+        let h = s / 2.0;
+        let color = to_hex(self.color);
+        let tile = match self.kind {
+            PatternKind::Stripes => {
+                format!(r#"<rect width="{}" height="{}" fill="{}"/>"#, s, h, color)
+            }
+            PatternKind::Dots => format!(
+                r#"<circle cx="{}" cy="{}" r="{}" fill="{}"/>"#,
+                h,
+                h,
+                s * DOT_RADIUS,
+                color
+            ),
+            PatternKind::Checker => format!(
+                r#"<path d="M0 0H{h}V{h}H0ZM{h} {h}H{s}V{s}H{h}Z" fill="{color}"/>"#,
+                h = h,
+                s = s,
+                color = color
+            ),
+            PatternKind::Hatch => {
+                // タイルの端で線が途切れないように、タイルの外まで伸ばした線を描く
+                let mut data = String::new();
+                for k in -1..=2 {
+                    let k = k as f32 * s;
+                    data.push_str(&format!("M{} {}L{} {}", -s, k + s, 2.0 * s, k - 2.0 * s));
+                    data.push_str(&format!("M{} {}L{} {}", -s, -s - k, 2.0 * s, 2.0 * s - k));
+                }
+                format!(
+                    r#"<path d="{}" stroke="{}" stroke-width="{}"/>"#,
+                    data,
+                    color,
+                    s * HATCH_WIDTH
+                )
+            }
+        };
+        let flip = if mirrored { " scale(-1 1)" } else { "" };
+        format!(
+            r#"<pattern id="{}" patternUnits="userSpaceOnUse" width="{}" height="{}" patternTransform="translate({:.1} {:.1}){} rotate({:.2})">{}</pattern>"#,
+            id,
+            s,
+            s,
+            center.0,
+            center.1,
+            flip,
+            angle.to_degrees(),
+            tile
+        )
+    }
+}
+
+// 描画先の座標に置いた模様
+pub struct PatternFrame {
+    kind: PatternKind,
+    spacing: f32,
+    center: (f32, f32),
+    sin: f32,
+    cos: f32,
+    mirrored: bool,
+}
+
+impl PatternFrame {
+    // 模様の輪郭までの符号付き距離を求める関数 (模様の内側が負)
+    pub fn distance(&self, x: f32, y: f32) -> f32 {
+        // 図形の中心を原点にし、図形の向きを u 軸にした座標に変える
+        let dx = if self.mirrored {
+            self.center.0 - x
+        } else {
+            x - self.center.0
+        };
+        let dy = y - self.center.1;
+        let u = dx * self.cos + dy * self.sin;
+        let v = dy * self.cos - dx * self.sin;
+
+        let s = self.spacing;
+        let h = s / 2.0;
+        match self.kind {
+            // v を s で割った余りが h 未満の所が縞
+            PatternKind::Stripes => {
+                let w = v.rem_euclid(s);
+                if w < h {
+                    -w.min(h - w)
+                } else {
+                    (w - h).min(s - w)
+                }
+            }
+            PatternKind::Dots => {
+                let (du, dv) = (u.rem_euclid(s) - h, v.rem_euclid(s) - h);
+                du.hypot(dv) - s * DOT_RADIUS
+            }
+            // 一辺が h のます目のうち、(0, 0) から始まるますと同じ色のます
+            PatternKind::Checker => {
+                let to_edge = (u - h * (u / h).round())
+                    .abs()
+                    .min((v - h * (v / h).round()).abs());
+                let parity = ((u / h).floor() + (v / h).floor()).rem_euclid(2.0);
+                if parity < 1.0 {
+                    -to_edge
+                } else {
+                    to_edge
+                }
+            }
+            // u + v と u - v が s の倍数になる2組の線
+            PatternKind::Hatch => {
+                let to_multiple = |t: f32| (t - s * (t / s).round()).abs();
+                let to_line = to_multiple(u + v).min(to_multiple(u - v)) * FRAC_1_SQRT_2;
+                to_line - s * HATCH_WIDTH / 2.0
+            }
+        }
+    }
+}
+
+// 模様の種類の選び方
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PatternStyle {
+    Kind(PatternKind),
+    // 図形ごとに種類を選ぶ
+    Mixed,
+}
+
+impl PatternStyle {
+    // 名前から模様の種類の選び方を選ぶ関数
+    pub fn from_name(name: &str) -> Option<PatternStyle> {
+        match name {
+            "stripes" => Some(PatternStyle::Kind(PatternKind::Stripes)),
+            "dots" => Some(PatternStyle::Kind(PatternKind::Dots)),
+            "checker" => Some(PatternStyle::Kind(PatternKind::Checker)),
+            "hatch" => Some(PatternStyle::Kind(PatternKind::Hatch)),
+            "mixed" => Some(PatternStyle::Mixed),
+            _ => None,
+        }
+    }
+
+    // color の色で塗った図形に描くランダムな模様を作る関数
+    // 模様の色は、パレットの color 以外の色から選ぶ
+    pub fn random<R: Rng>(
+        self,
+        rng: &mut R,
+        colors: &[Rgb<u8>],
+        color: Rgb<u8>,
+        spacing: f32,
+    ) -> Pattern {
+        let kind = match self {
+            PatternStyle::Kind(kind) => kind,
+            // usize はプラットフォームによって乱数列が変わるので u32 で選ぶ
+            PatternStyle::Mixed => KINDS[rng.gen_range(0..KINDS.len() as u32) as usize],
+        };
+        Pattern {
+            kind,
+            color: other_color(rng, colors, color),
+            spacing,
+        }
+    }
+}
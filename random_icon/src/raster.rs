@@ -0,0 +1,319 @@
+use crate::gradient::Ramp;
+use crate::pattern::PatternFrame;
+use crate::shape::Geometry;
+use crate::text;
+use crate::{Composition, ShapeSpec, ICON_SIZE};
+use image::{Rgb, Rgba, RgbaImage};
+
+// 構図を画像に描画する関数
+pub fn draw_icon(composition: &Composition, size: u32, antialias: bool) -> RgbaImage {
+    let mut img = match (composition.background, composition.background_gradient) {
+        (Some(color), Some(gradient)) => {
+            let paint = Paint::Gradient {
+                from: to_rgba(color, 1.0),
+                to: to_rgba(gradient.to(), 1.0),
+                ramp: gradient.background_ramp(size as f32),
+            };
+            RgbaImage::from_fn(size, size, |x, y| paint.at(x as f32 + 0.5, y as f32 + 0.5))
+        }
+        (Some(color), None) => RgbaImage::from_pixel(size, size, to_rgba(color, 1.0)),
+        (None, _) => RgbaImage::from_pixel(size, size, Rgba([0, 0, 0, 0])),
+    };
+    let scale = size as f32 / ICON_SIZE as f32;
+
+    for shape in composition.drawn_shapes() {
+        let geometry = shape.geometry(scale);
+        let paint = shape_paint(&shape, scale);
+        let stroke = shape.stroke.as_ref();
+        if !stroke.is_some_and(|stroke| stroke.line_art) {
+            draw_shape(&mut img, geometry.clone(), &Brush::Fill(&paint), antialias);
+            // 模様は塗りの上に同じ輪郭でもう一度塗る
+            if let Some(pattern) = shape.pattern {
+                let pattern_paint = Paint::Pattern {
+                    color: to_rgba(pattern.color, shape.opacity),
+                    frame: pattern.frame(shape.position, shape.angle, shape.mirrored, scale),
+                    antialias,
+                };
+                draw_shape(
+                    &mut img,
+                    geometry.clone(),
+                    &Brush::Fill(&pattern_paint),
+                    antialias,
+                );
+            }
+        }
+        if let Some(stroke) = stroke {
+            // 線の色がなければ塗りと同じ色で描く
+            let stroke_paint = match stroke.color {
+                Some(color) => Paint::Solid(to_rgba(color, shape.opacity)),
+                None => paint,
+            };
+            let brush = Brush::Stroke(&stroke_paint, stroke.width * scale);
+            draw_shape(&mut img, geometry, &brush, antialias);
+        }
+    }
+    if let Some(overlay) = &composition.overlay {
+        let glyphs = text::layout_overlay(&overlay.text, size as f32);
+        text::draw_glyphs(&mut img, &glyphs, to_rgba(overlay.color, 1.0), antialias);
+    }
+    if let Some(mask) = composition.mask {
+        mask.apply(&mut img, antialias);
+    }
+
+    img
+}
+
+// 図形の塗りを作る関数
+fn shape_paint(shape: &ShapeSpec, scale: f32) -> Paint {
+    let from = to_rgba(shape.color, shape.opacity);
+    match shape.gradient {
+        Some(gradient) => Paint::Gradient {
+            from,
+            to: to_rgba(gradient.to(), shape.opacity),
+            ramp: gradient.shape_ramp(
+                (shape.position.0 * scale, shape.position.1 * scale),
+                shape.radius() * scale,
+                shape.angle,
+                shape.mirrored,
+            ),
+        },
+        None => Paint::Solid(from),
+    }
+}
+
+// 塗り
+// グラデーションや模様なら画素ごとに色が変わる
+pub enum Paint {
+    Solid(Rgba<u8>),
+    Gradient {
+        from: Rgba<u8>,
+        to: Rgba<u8>,
+        ramp: Ramp,
+    },
+    // 模様の内側だけを color で塗り、外側は透明にする
+    Pattern {
+        color: Rgba<u8>,
+        frame: PatternFrame,
+        antialias: bool,
+    },
+}
+
+impl Paint {
+    // 座標の色を求める関数
+    fn at(&self, x: f32, y: f32) -> Rgba<u8> {
+        match self {
+            Paint::Solid(color) => *color,
+            Paint::Gradient { from, to, ramp } => {
+                let t = ramp.position(x, y);
+                Rgba(
+                    [0, 1, 2, 3].map(|i| {
+                        (from[i] as f32 + (to[i] as f32 - from[i] as f32) * t).round() as u8
+                    }),
+                )
+            }
+            Paint::Pattern {
+                color,
+                frame,
+                antialias,
+            } => {
+                let d = frame.distance(x, y);
+                let coverage = if *antialias {
+                    (0.5 - d).clamp(0.0, 1.0)
+                } else if d <= 0.0 {
+                    1.0
+                } else {
+                    0.0
+                };
+                let mut color = *color;
+                color[3] = (color[3] as f32 * coverage).round() as u8;
+                color
+            }
+        }
+    }
+}
+
+// 図形の描き方
+pub enum Brush<'a> {
+    // 内側を塗る
+    Fill(&'a Paint),
+    // 輪郭を中心に、指定した幅の線を描く
+    Stroke(&'a Paint, f32),
+}
+
+// 色と不透明度からRGBAの色を作る関数
+fn to_rgba(color: Rgb<u8>, opacity: f32) -> Rgba<u8> {
+    let [r, g, b] = color.0;
+    Rgba([r, g, b, (opacity * 255.0).round() as u8])
+}
+
+// 図形を塗りつぶすか、輪郭の線を描く関数
+pub fn draw_shape(img: &mut RgbaImage, geometry: Geometry, brush: &Brush, antialias: bool) {
+    match geometry {
+        Geometry::Circle { center, radius } => draw_circle(img, center, radius, brush, antialias),
+        Geometry::SemiCircle {
+            center,
+            radius,
+            angle,
+        } => draw_semi_circle(img, center, radius, angle, brush, antialias),
+        Geometry::Polygons(rings) => fill_polygons(img, &rings, brush, antialias),
+    }
+}
+
+// 円を描画する関数
+fn draw_circle(
+    img: &mut RgbaImage,
+    position: (f32, f32),
+    size: f32,
+    brush: &Brush,
+    antialias: bool,
+) {
+    let (cx, cy) = position;
+    let bounds = (cx - size, cy - size, cx + size, cy + size);
+    fill_shape(img, bounds, brush, antialias, |x, y| {
+        (x - cx).hypot(y - cy) - size
+    });
+}
+
+// 半円を描画する関数
+fn draw_semi_circle(
+    img: &mut RgbaImage,
+    position: (f32, f32),
+    size: f32,
+    angle: f32,
+    brush: &Brush,
+    antialias: bool,
+) {
+    let (cx, cy) = position;
+    let (sin, cos) = angle.sin_cos();
+    let bounds = (cx - size, cy - size, cx + size, cy + size);
+    // 円の内側かつ angle の向きと反対側の半平面
+    fill_shape(img, bounds, brush, antialias, |x, y| {
+        let dx = x - cx;
+        let dy = y - cy;
+        (dx.hypot(dy) - size).max(dx * cos + dy * sin)
+    });
+}
+
+// 多角形の集まりを偶奇規則で塗りつぶす関数
+pub fn fill_polygons(
+    img: &mut RgbaImage,
+    rings: &[Vec<(f32, f32)>],
+    brush: &Brush,
+    antialias: bool,
+) {
+    let rings: Vec<&[(f32, f32)]> = rings
+        .iter()
+        .filter(|ring| ring.len() >= 3)
+        .map(|ring| ring.as_slice())
+        .collect();
+    if rings.is_empty() {
+        return;
+    }
+    let bounds = rings.iter().flat_map(|ring| ring.iter()).fold(
+        (f32::MAX, f32::MAX, f32::MIN, f32::MIN),
+        |(min_x, min_y, max_x, max_y), &(x, y)| {
+            (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+        },
+    );
+    fill_shape(img, bounds, brush, antialias, |x, y| {
+        polygon_distance(&rings, x, y)
+    });
+}
+
+// 多角形の辺までの符号付き距離を求める関数 (内側が負)
+fn polygon_distance(rings: &[&[(f32, f32)]], x: f32, y: f32) -> f32 {
+    let mut distance_squared = f32::MAX;
+    let mut inside = false;
+    for points in rings {
+        for i in 0..points.len() {
+            let (x1, y1) = points[i];
+            let (x2, y2) = points[(i + 1) % points.len()];
+
+            // 辺までの距離
+            let (ex, ey) = (x2 - x1, y2 - y1);
+            let (wx, wy) = (x - x1, y - y1);
+            let t = ((wx * ex + wy * ey) / (ex * ex + ey * ey)).clamp(0.0, 1.0);
+            let (dx, dy) = (wx - ex * t, wy - ey * t);
+            distance_squared = distance_squared.min(dx * dx + dy * dy);
+
+            // 偶奇規則で内外を判定する
+            if (y1 <= y && y < y2) || (y2 <= y && y < y1) {
+                let cross_x = x1 + (y - y1) * ex / ey;
+                if x < cross_x {
+                    inside = !inside;
+                }
+            }
+        }
+    }
+
+    let distance = distance_squared.sqrt();
+    if inside {
+        -distance
+    } else {
+        distance
+    }
+}
+
+// 符号付き距離関数で表した図形を塗りつぶす関数
+// 画素の中心から輪郭までの距離で塗る割合を決め、antialias が false なら内側の画素だけを塗る
+// 線を描く場合は、輪郭からの距離が線の幅の半分までの所を塗る
+fn fill_shape<F: Fn(f32, f32) -> f32>(
+    img: &mut RgbaImage,
+    bounds: (f32, f32, f32, f32),
+    brush: &Brush,
+    antialias: bool,
+    distance: F,
+) {
+    let (paint, half_width) = match *brush {
+        Brush::Fill(paint) => (paint, None),
+        Brush::Stroke(paint, width) => (paint, Some(width / 2.0)),
+    };
+    let margin = half_width.unwrap_or(0.0);
+    let (min_x, min_y, max_x, max_y) = bounds;
+    let x_range = pixel_range(min_x - margin, max_x + margin, img.width());
+    let y_range = pixel_range(min_y - margin, max_y + margin, img.height());
+
+    for y in y_range {
+        for x in x_range.clone() {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let d = match half_width {
+                Some(half_width) => distance(px, py).abs() - half_width,
+                None => distance(px, py),
+            };
+            let coverage = if antialias {
+                (0.5 - d).clamp(0.0, 1.0)
+            } else if d <= 0.0 {
+                1.0
+            } else {
+                0.0
+            };
+            if coverage > 0.0 {
+                blend_pixel(img, x, y, paint.at(px, py), coverage);
+            }
+        }
+    }
+}
+
+// 範囲に含まれる画素の座標を画像の内側に切り詰める関数
+fn pixel_range(min: f32, max: f32, limit: u32) -> std::ops::Range<u32> {
+    let start = (min - 1.0).floor().max(0.0) as u32;
+    let end = ((max + 1.0).ceil().max(0.0) as u32 + 1).min(limit);
+    start..end.max(start)
+}
+
+// 塗る割合に応じて画素に色を重ねる関数
+pub fn blend_pixel(img: &mut RgbaImage, x: u32, y: u32, color: Rgba<u8>, coverage: f32) {
+    let pixel = img.get_pixel_mut(x, y);
+    let src_alpha = color[3] as f32 / 255.0 * coverage;
+    let dst_alpha = pixel[3] as f32 / 255.0;
+    let out_alpha = src_alpha + dst_alpha * (1.0 - src_alpha);
+    if out_alpha <= 0.0 {
+        return;
+    }
+    for i in 0..3 {
+        let src = color[i] as f32 * src_alpha;
+        let dst = pixel[i] as f32 * dst_alpha * (1.0 - src_alpha);
+        pixel[i] = ((src + dst) / out_alpha).round() as u8;
+    }
+    pixel[3] = (out_alpha * 255.0).round() as u8;
+}
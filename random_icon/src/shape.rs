@@ -0,0 +1,386 @@
+use crate::path;
+use std::f32::consts::PI;
+use std::sync::OnceLock;
+
+// 図形の形
+// 独自の図形はこのトレイトを実装して ShapeRegistry に登録する
+pub trait Shape: Send + Sync {
+    // レシピや shapes パラメータで使う名前
+    fn name(&self) -> &str;
+
+    // 図形がすっぽり収まる円の半径を求める関数
+    fn radius(&self, size: f32) -> f32;
+
+    // 中心、大きさ、向きを指定したときの図形の輪郭を求める関数
+    fn geometry(&self, center: (f32, f32), size: f32, angle: f32) -> Geometry;
+}
+
+// 描画する図形の輪郭
+// 座標は描画先の座標で、ラスタ画像とSVGのどちらにもこの輪郭から描画する
+#[derive(Clone)]
+pub enum Geometry {
+    Circle {
+        center: (f32, f32),
+        radius: f32,
+    },
+    // angle の向きと反対側の半円
+    SemiCircle {
+        center: (f32, f32),
+        radius: f32,
+        angle: f32,
+    },
+    // 多角形の集まり
+    // 内外は偶奇規則で決めるので、内側の多角形で穴をあけられる
+    Polygons(Vec<Vec<(f32, f32)>>),
+}
+
+impl Geometry {
+    // 縦の直線 x = axis で左右を裏返した輪郭を求める関数
+    pub fn mirrored(self, axis: f32) -> Geometry {
+        let flip = |(x, y): (f32, f32)| (2.0 * axis - x, y);
+        match self {
+            Geometry::Circle { center, radius } => Geometry::Circle {
+                center: flip(center),
+                radius,
+            },
+            Geometry::SemiCircle {
+                center,
+                radius,
+                angle,
+            } => Geometry::SemiCircle {
+                center: flip(center),
+                radius,
+                angle: PI - angle,
+            },
+            Geometry::Polygons(rings) => Geometry::Polygons(
+                rings
+                    .into_iter()
+                    .map(|ring| ring.into_iter().map(flip).collect())
+                    .collect(),
+            ),
+        }
+    }
+}
+
+// 円
+pub struct Circle;
+
+impl Shape for Circle {
+    fn name(&self) -> &str {
+        "circle"
+    }
+
+    fn radius(&self, size: f32) -> f32 {
+        size
+    }
+
+    fn geometry(&self, center: (f32, f32), size: f32, _angle: f32) -> Geometry {
+        Geometry::Circle {
+            center,
+            radius: size,
+        }
+    }
+}
+
+// 半円
+pub struct SemiCircle;
+
+impl Shape for SemiCircle {
+    fn name(&self) -> &str {
+        "semi_circle"
+    }
+
+    fn radius(&self, size: f32) -> f32 {
+        size
+    }
+
+    fn geometry(&self, center: (f32, f32), size: f32, angle: f32) -> Geometry {
+        Geometry::SemiCircle {
+            center,
+            radius: size,
+            angle,
+        }
+    }
+}
+
+// 正多角形
+pub struct RegularPolygon {
+    pub name: String,
+    pub sides: u32,
+    // size に対する頂点までの距離の割合
+    pub radius: f32,
+}
+
+impl Shape for RegularPolygon {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn radius(&self, size: f32) -> f32 {
+        size * self.radius
+    }
+
+    fn geometry(&self, center: (f32, f32), size: f32, angle: f32) -> Geometry {
+        Geometry::Polygons(vec![regular_polygon(
+            center,
+            size * self.radius,
+            self.sides,
+            angle,
+        )])
+    }
+}
+
+// 星形
+pub struct Star {
+    pub name: String,
+    pub points: u32,
+    // size に対する外側の頂点までの距離の割合
+    pub radius: f32,
+    // 外側の頂点に対する内側の頂点までの距離の割合
+    pub inner: f32,
+}
+
+impl Shape for Star {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn radius(&self, size: f32) -> f32 {
+        size * self.radius
+    }
+
+    fn geometry(&self, center: (f32, f32), size: f32, angle: f32) -> Geometry {
+        Geometry::Polygons(vec![star_polygon(
+            center,
+            size * self.radius,
+            self.inner,
+            self.points,
+            angle,
+        )])
+    }
+}
+
+// SVG のパスデータから作る図形
+// パスの外接矩形の中心を図形の中心にし、中心から最も遠い点までの距離が size になるように拡大縮小する
+pub struct PathShape {
+    name: String,
+    // 中心を原点とし、最も遠い点までの距離を 1 にした多角形
+    rings: Vec<Vec<(f32, f32)>>,
+}
+
+impl PathShape {
+    // パスデータを読み込む関数
+    // 曲線は折れ線に近似し、それぞれのサブパスは閉じた多角形として扱う
+    pub fn new(name: &str, data: &str) -> Result<PathShape, String> {
+        let rings = path::parse_path(data)?;
+        let points = rings.iter().flatten();
+        let (min_x, min_y, max_x, max_y) = points.clone().fold(
+            (f32::MAX, f32::MAX, f32::MIN, f32::MIN),
+            |(min_x, min_y, max_x, max_y), &(x, y)| {
+                (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+            },
+        );
+        let center = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+        let radius = points
+            .map(|&(x, y)| (x - center.0).hypot(y - center.1))
+            .fold(0.0, f32::max);
+        if !(radius.is_finite() && radius > 0.0) {
+            return Err(format!("path of shape \"{}\" has no area", name));
+        }
+
+        let rings = rings
+            .into_iter()
+            .map(|ring| {
+                ring.into_iter()
+                    .map(|(x, y)| ((x - center.0) / radius, (y - center.1) / radius))
+                    .collect()
+            })
+            .collect();
+        Ok(PathShape {
+            name: name.to_string(),
+            rings,
+        })
+    }
+}
+
+impl Shape for PathShape {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn radius(&self, size: f32) -> f32 {
+        size
+    }
+
+    fn geometry(&self, center: (f32, f32), size: f32, angle: f32) -> Geometry {
+        let (sin, cos) = angle.sin_cos();
+        let rings = self
+            .rings
+            .iter()
+            .map(|ring| {
+                ring.iter()
+                    .map(|&(x, y)| {
+                        (
+                            center.0 + size * (x * cos - y * sin),
+                            center.1 + size * (x * sin + y * cos),
+                        )
+                    })
+                    .collect()
+            })
+            .collect();
+        Geometry::Polygons(rings)
+    }
+}
+
+// 使える図形の一覧
+pub struct ShapeRegistry {
+    shapes: Vec<Box<dyn Shape>>,
+}
+
+impl ShapeRegistry {
+    // 空の一覧を作る関数
+    pub fn new() -> ShapeRegistry {
+        ShapeRegistry { shapes: Vec::new() }
+    }
+
+    // 組み込みの図形を登録した一覧を作る関数
+    pub fn with_builtin_shapes() -> ShapeRegistry {
+        let polygon = |name: &str, sides, radius| RegularPolygon {
+            name: name.to_string(),
+            sides,
+            radius,
+        };
+        ShapeRegistry {
+            shapes: vec![
+                Box::new(Circle),
+                Box::new(SemiCircle),
+                // 四角形だけは size の半分を頂点までの距離にしている
+                Box::new(polygon("square", 4, 0.5)),
+                Box::new(polygon("pentagon", 5, 1.0)),
+                Box::new(polygon("hexagon", 6, 1.0)),
+                Box::new(polygon("triangle", 3, 1.0)),
+                Box::new(polygon("octagon", 8, 1.0)),
+                Box::new(Star {
+                    name: "star".to_string(),
+                    points: 5,
+                    radius: 1.0,
+                    inner: 0.5,
+                }),
+            ],
+        }
+    }
+
+    // 図形を登録する関数
+    // 同じ名前の図形がすでにあれば登録しない
+    pub fn register(&mut self, shape: Box<dyn Shape>) -> Result<(), String> {
+        // 名前は shapes パラメータでカンマ区切りにして指定し、前後の空白は取り除かれる
+        let name = shape.name();
+        if name.is_empty() || name.contains(',') || name.trim() != name {
+            return Err(format!("invalid shape name: \"{}\"", name));
+        }
+        if self.find(name).is_some() {
+            return Err(format!("shape \"{}\" is already registered", name));
+        }
+        self.shapes.push(shape);
+        Ok(())
+    }
+
+    // 名前から図形を探す関数
+    pub fn find(&self, name: &str) -> Option<&dyn Shape> {
+        self.shapes
+            .iter()
+            .find(|shape| shape.name() == name)
+            .map(|shape| shape.as_ref())
+    }
+
+    // 登録されている図形を登録した順に返す関数
+    pub fn shapes(&self) -> impl Iterator<Item = &dyn Shape> {
+        self.shapes.iter().map(|shape| shape.as_ref())
+    }
+}
+
+impl Default for ShapeRegistry {
+    fn default() -> ShapeRegistry {
+        ShapeRegistry::new()
+    }
+}
+
+// サーバー全体で使う図形の一覧
+static REGISTRY: OnceLock<ShapeRegistry> = OnceLock::new();
+
+// サーバーで使う図形の一覧を設定する関数
+// 最初にアイコンを生成する前に呼ぶ必要があり、すでに設定されていれば渡した一覧を返す
+pub fn install(registry: ShapeRegistry) -> Result<(), ShapeRegistry> {
+    REGISTRY.set(registry)
+}
+
+// サーバーで使う図形の一覧を返す関数
+// install されていなければ組み込みの図形だけを使う
+pub fn registry() -> &'static ShapeRegistry {
+    REGISTRY.get_or_init(ShapeRegistry::with_builtin_shapes)
+}
+
+// shapes パラメータを省略したときに選ぶ図形
+// 以前からある5種類に限ることで、同じシードからは以前と同じアイコンができる
+pub const DEFAULT_SHAPES: [&str; 5] = ["circle", "semi_circle", "square", "pentagon", "hexagon"];
+
+// 正多角形の頂点を求める関数
+pub fn regular_polygon(center: (f32, f32), radius: f32, sides: u32, angle: f32) -> Vec<(f32, f32)> {
+    (0..sides)
+        .map(|i| {
+            let theta = angle + (PI * 2.0 / sides as f32) * (i as f32);
+            point_on_circle(center, radius, theta)
+        })
+        .collect()
+}
+
+// 星形の頂点を求める関数
+// 外側の頂点と内側の頂点を交互に並べる
+pub fn star_polygon(
+    center: (f32, f32),
+    radius: f32,
+    inner: f32,
+    points: u32,
+    angle: f32,
+) -> Vec<(f32, f32)> {
+    (0..points * 2)
+        .map(|i| {
+            let theta = angle + (PI / points as f32) * (i as f32);
+            let r = if i % 2 == 0 { radius } else { radius * inner };
+            point_on_circle(center, r, theta)
+        })
+        .collect()
+}
+
+// 円周上の点を求める関数
+pub fn point_on_circle(center: (f32, f32), radius: f32, theta: f32) -> (f32, f32) {
+    (
+        center.0 + radius * theta.cos(),
+        center.1 + radius * theta.sin(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE: &str = "M0 0H10V10H0Z";
+
+    #[test]
+    fn register_rejects_names_that_shapes_cannot_select() {
+        let mut registry = ShapeRegistry::with_builtin_shapes();
+        for name in ["", "a,b", " leaf", "leaf ", "\tleaf"] {
+            let shape = PathShape::new(name, SQUARE).unwrap();
+            assert!(registry.register(Box::new(shape)).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn register_rejects_duplicate_names() {
+        let mut registry = ShapeRegistry::with_builtin_shapes();
+        let leaf = || Box::new(PathShape::new("leaf", SQUARE).unwrap());
+        assert!(registry.register(leaf()).is_ok());
+        assert!(registry.register(leaf()).is_err());
+        assert!(registry.find("leaf").is_some());
+    }
+}
@@ -0,0 +1,256 @@
+use crate::text;
+use image::{imageops, Rgba, RgbaImage};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use uuid::Builder;
+
+// 1枚のシートに並べられるアイコンの数の上限
+pub const MAX_SHEET_ICONS: usize = 256;
+// シートの一辺の長さの上限
+const MAX_SHEET_SIZE: u32 = 8192;
+// size を省略したときのアイコンの一辺のピクセル数
+pub const DEFAULT_SPRITE_SIZE: u32 = 64;
+// ラベルの文字色
+const LABEL_COLOR: Rgba<u8> = Rgba([0x33, 0x33, 0x33, 0xff]);
+
+// シートのクエリパラメータ
+// アイコンの見た目に関するパラメータは IconQuery で受け取る
+#[derive(Deserialize)]
+pub struct SheetQuery {
+    // カンマ区切りの識別子
+    identifiers: Option<String>,
+    // identifiers の代わりにランダムな識別子で並べるアイコンの数
+    pub count: Option<usize>,
+    // 1行に並べるアイコンの数
+    columns: Option<u32>,
+    // true ならアイコンの下に識別子を描く
+    labels: Option<bool>,
+    // アイコンの間隔のピクセル数
+    gap: Option<u32>,
+}
+
+// シートに並べるアイコンとその配置
+pub struct SheetLayout {
+    pub identifiers: Vec<String>,
+    pub size: u32,
+    columns: u32,
+    rows: u32,
+    gap: u32,
+    // ラベルを描く欄の高さ (ラベルがなければ 0)
+    label_height: u32,
+}
+
+impl SheetLayout {
+    // クエリパラメータからアイコンの配置を決める関数
+    // count で指定した場合の識別子は seed から決まる
+    pub fn new<R: Rng>(query: &SheetQuery, size: u32, rng: &mut R) -> Result<SheetLayout, String> {
+        let identifiers: Vec<String> = match (&query.identifiers, query.count) {
+            (Some(list), None) => {
+                let identifiers: Vec<String> =
+                    list.split(',').map(|id| id.trim().to_string()).collect();
+                check_sheet_icons(identifiers.len())?;
+                identifiers
+            }
+            (None, Some(count)) => {
+                check_sheet_icons(count)?;
+                (0..count)
+                    .map(|_| {
+                        Builder::from_random_bytes(rng.gen())
+                            .into_uuid()
+                            .to_string()
+                    })
+                    .collect()
+            }
+            _ => return Err("specify either identifiers or count".to_string()),
+        };
+        if identifiers.iter().any(|id| id.is_empty()) {
+            return Err("identifiers must not be empty".to_string());
+        }
+
+        let count = identifiers.len() as u32;
+        // 省略すればなるべく正方形に近くなるように並べる
+        let columns = query
+            .columns
+            .unwrap_or_else(|| (count as f32).sqrt().ceil() as u32)
+            .clamp(1, count);
+        let rows = count.div_ceil(columns);
+        let labels = query.labels.unwrap_or(false);
+        let gap = query.gap.unwrap_or(if labels { size / 8 } else { 0 });
+        // シートの大きさを計算するときに桁あふれしないように、先に間隔を確かめる
+        if gap > MAX_SHEET_SIZE {
+            return Err(format!("gap must be at most {}: {}", MAX_SHEET_SIZE, gap));
+        }
+        let label_height = if labels {
+            (label_px(size) * 1.5).ceil() as u32
+        } else {
+            0
+        };
+
+        let layout = SheetLayout {
+            identifiers,
+            size,
+            columns,
+            rows,
+            gap,
+            label_height,
+        };
+        let (width, height) = layout.dimensions();
+        if width > MAX_SHEET_SIZE || height > MAX_SHEET_SIZE {
+            return Err(format!(
+                "the sheet would be {}x{} pixels, but at most {}x{} is supported",
+                width, height, MAX_SHEET_SIZE, MAX_SHEET_SIZE
+            ));
+        }
+        Ok(layout)
+    }
+
+    // シート全体の幅と高さを求める関数
+    pub fn dimensions(&self) -> (u32, u32) {
+        let cell_height = self.size + self.label_height;
+        (
+            self.columns * self.size + (self.columns - 1) * self.gap,
+            self.rows * cell_height + (self.rows - 1) * self.gap,
+        )
+    }
+
+    // i 番目のアイコンの左上の座標を求める関数
+    pub fn offset(&self, i: usize) -> (u32, u32) {
+        let column = i as u32 % self.columns;
+        let row = i as u32 / self.columns;
+        (
+            column * (self.size + self.gap),
+            row * (self.size + self.label_height + self.gap),
+        )
+    }
+}
+
+// シートに並べるアイコンの数を確かめる関数
+fn check_sheet_icons(count: usize) -> Result<(), String> {
+    if (1..=MAX_SHEET_ICONS).contains(&count) {
+        Ok(())
+    } else {
+        Err(format!(
+            "a sheet must contain between 1 and {} icons: {}",
+            MAX_SHEET_ICONS, count
+        ))
+    }
+}
+
+// アイコンの大きさに合わせたラベルの文字の大きさを求める関数
+fn label_px(size: u32) -> f32 {
+    (size as f32 * 0.18).clamp(10.0, 24.0)
+}
+
+// アイコンを格子状に並べたシートを描画する関数
+// 背景は透明で、ラベルを付けるならアイコンの下に識別子を描く
+pub fn draw_sheet(layout: &SheetLayout, icons: &[RgbaImage]) -> RgbaImage {
+    let (width, height) = layout.dimensions();
+    let mut sheet = RgbaImage::new(width, height);
+    let px = label_px(layout.size);
+
+    for (i, (icon, identifier)) in icons.iter().zip(&layout.identifiers).enumerate() {
+        let (x, y) = layout.offset(i);
+        imageops::replace(&mut sheet, icon, x as i64, y as i64);
+        if layout.label_height > 0 {
+            let label = text::fit_label(identifier, px, layout.size as f32);
+            // ラベルの欄の中で文字の高さがおおよそ中央に来る位置をベースラインにする
+            let baseline = (y + layout.size) as f32 + (layout.label_height as f32 + px * 0.7) / 2.0;
+            let center = x as f32 + layout.size as f32 / 2.0;
+            let glyphs = text::layout_label(&label, px, center, baseline);
+            text::draw_glyphs(&mut sheet, &glyphs, LABEL_COLOR, true);
+        }
+    }
+    sheet
+}
+
+// スプライトマップ
+#[derive(Serialize)]
+pub struct SpriteMap<'a> {
+    // シートの画像の URL
+    image: &'a str,
+    width: u32,
+    height: u32,
+    sprites: Vec<Sprite<'a>>,
+}
+
+// シートの中の1つのアイコンの位置
+#[derive(Serialize)]
+struct Sprite<'a> {
+    identifier: &'a str,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+// シートの中のアイコンの位置の一覧を作る関数
+pub fn sprite_map<'a>(layout: &'a SheetLayout, image: &'a str) -> SpriteMap<'a> {
+    let (width, height) = layout.dimensions();
+    let sprites = layout
+        .identifiers
+        .iter()
+        .enumerate()
+        .map(|(i, identifier)| {
+            let (x, y) = layout.offset(i);
+            Sprite {
+                identifier,
+                x,
+                y,
+                width: layout.size,
+                height: layout.size,
+            }
+        })
+        .collect();
+    SpriteMap {
+        image,
+        width,
+        height,
+        sprites,
+    }
+}
+
+// シートを背景画像として使う CSS を作る関数
+// .icon に共通の指定を、.icon-{identifier} にそれぞれの位置を書く
+pub fn sprite_css(layout: &SheetLayout, image: &str) -> String {
+    let mut css = String::new();
+    let _ = writeln!(
+        css,
+        ".icon {{ display: inline-block; width: {size}px; height: {size}px; background-image: url(\"{image}\"); background-repeat: no-repeat; }}",
+        size = layout.size,
+        image = image.replace('\\', "%5C").replace('"', "%22")
+    );
+    for (i, identifier) in layout.identifiers.iter().enumerate() {
+        let (x, y) = layout.offset(i);
+        let _ = writeln!(
+            css,
+            ".icon-{} {{ background-position: {} {}; }}",
+            css_escape(identifier),
+            css_offset(x),
+            css_offset(y)
+        );
+    }
+    css
+}
+
+// 背景の位置を CSS の値にする関数
+fn css_offset(offset: u32) -> String {
+    if offset == 0 {
+        "0".to_string()
+    } else {
+        format!("-{}px", offset)
+    }
+}
+
+// 識別子をクラス名に使えるようにエスケープする関数
+fn css_escape(identifier: &str) -> String {
+    let mut escaped = String::new();
+    for c in identifier.chars() {
+        if c.is_ascii_alphanumeric() || c == '-' || c == '_' || !c.is_ascii() {
+            escaped.push(c);
+        } else {
+            let _ = write!(escaped, "\\{:x} ", c as u32);
+        }
+    }
+    escaped
+}
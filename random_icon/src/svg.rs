@@ -0,0 +1,224 @@
+use crate::color::to_hex;
+use crate::gradient::Ramp;
+use crate::shape::{point_on_circle, Geometry};
+use crate::text;
+use crate::{Composition, ShapeSpec, ICON_SIZE};
+use image::Rgb;
+use std::f32::consts::PI;
+use std::fmt::Write;
+
+// 構図をSVGに変換する関数
+// viewBox は ICON_SIZE 四方で、width と height に出力サイズを指定する
+pub fn render_svg(composition: &Composition, size: u32) -> String {
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{size}" height="{size}" viewBox="0 0 {icon} {icon}">"#,
+        size = size,
+        icon = ICON_SIZE
+    );
+    // アイコンの外にはみ出した部分と、切り抜く形の外側を切り取る
+    let clip = match composition.mask {
+        Some(mask) => mask.svg_element(ICON_SIZE),
+        None => format!(
+            r#"<rect width="{icon}" height="{icon}"/>"#,
+            icon = ICON_SIZE
+        ),
+    };
+    let _ = write!(
+        svg,
+        r#"<defs><clipPath id="icon-clip">{}</clipPath></defs><g clip-path="url(#icon-clip)">"#,
+        clip
+    );
+    if let Some(background) = composition.background {
+        let paint = match composition.background_gradient {
+            Some(gradient) => {
+                let id = "background-gradient";
+                let ramp = gradient.background_ramp(ICON_SIZE as f32);
+                svg.push_str(&gradient_element(id, background, gradient.to(), &ramp));
+                format!("url(#{})", id)
+            }
+            None => to_hex(background),
+        };
+        let _ = write!(
+            svg,
+            r#"<rect width="{icon}" height="{icon}" fill="{paint}"/>"#,
+            icon = ICON_SIZE,
+            paint = paint
+        );
+    }
+    for (i, shape) in composition.drawn_shapes().iter().enumerate() {
+        // グラデーションと模様は使う図形の直前に定義する
+        let paint = match shape.gradient {
+            Some(gradient) => {
+                let id = format!("gradient-{}", i);
+                let ramp = gradient.shape_ramp(
+                    shape.position,
+                    shape.radius(),
+                    shape.angle,
+                    shape.mirrored,
+                );
+                svg.push_str(&gradient_element(&id, shape.color, gradient.to(), &ramp));
+                format!("url(#{})", id)
+            }
+            None => to_hex(shape.color),
+        };
+        let line_art = shape.stroke.as_ref().is_some_and(|stroke| stroke.line_art);
+        match shape.pattern {
+            // 模様は塗りの上に同じ図形を模様で塗って重ね、輪郭の線は模様の上に描く
+            Some(pattern) if !line_art => {
+                let id = format!("pattern-{}", i);
+                svg.push_str(&pattern.svg_element(
+                    &id,
+                    shape.position,
+                    shape.angle,
+                    shape.mirrored,
+                ));
+                svg.push_str(&shape_element(shape, &fill(&paint, shape.opacity)));
+                let mut attributes = fill(&format!("url(#{})", id), shape.opacity);
+                attributes.push_str(&stroke_attributes(shape, &paint));
+                svg.push_str(&shape_element(shape, &attributes));
+            }
+            _ => svg.push_str(&shape_element(shape, &paint_attributes(shape, &paint))),
+        }
+    }
+    if let Some(overlay) = &composition.overlay {
+        let glyphs = text::layout_overlay(&overlay.text, ICON_SIZE as f32);
+        let _ = write!(
+            svg,
+            r#"<path d="{}" fill="{}" aria-label="{}"/>"#,
+            text::overlay_path_data(&glyphs),
+            to_hex(overlay.color),
+            escape(&overlay.text)
+        );
+    }
+    svg.push_str("</g></svg>");
+    svg
+}
+
+// 図形をSVGの要素に変換する関数
+// fill には塗りの属性を渡す
+fn shape_element(shape: &ShapeSpec, fill: &str) -> String {
+    match shape.geometry(1.0) {
+        Geometry::Circle { center, radius } => format!(
+            r#"<circle cx="{:.1}" cy="{:.1}" r="{:.1}"{}/>"#,
+            center.0, center.1, radius, fill
+        ),
+        Geometry::SemiCircle {
+            center,
+            radius,
+            angle,
+        } => {
+            // angle と反対側の半分を描く
+            let (x1, y1) = point_on_circle(center, radius, angle + PI / 2.0);
+            let (x2, y2) = point_on_circle(center, radius, angle + 3.0 * PI / 2.0);
+            format!(
+                r#"<path d="M{:.1} {:.1}A{:.1} {:.1} 0 0 1 {:.1} {:.1}Z"{}/>"#,
+                x1, y1, radius, radius, x2, y2, fill
+            )
+        }
+        Geometry::Polygons(rings) => polygon_element(&rings, fill),
+    }
+}
+
+// 多角形をSVGの要素に変換する関数
+// 複数の多角形はラスタ画像と同じく偶奇規則で塗るパスにする
+fn polygon_element(rings: &[Vec<(f32, f32)>], fill: &str) -> String {
+    let point_list = |ring: &[(f32, f32)]| -> Vec<String> {
+        ring.iter()
+            .map(|(x, y)| format!("{:.1},{:.1}", x, y))
+            .collect()
+    };
+    match rings {
+        [ring] => format!(
+            r#"<polygon points="{}"{}/>"#,
+            point_list(ring).join(" "),
+            fill
+        ),
+        _ => {
+            let data: Vec<String> = rings
+                .iter()
+                .map(|ring| format!("M{}Z", point_list(ring).join("L")))
+                .collect();
+            format!(
+                r#"<path d="{}" fill-rule="evenodd"{}/>"#,
+                data.join(""),
+                fill
+            )
+        }
+    }
+}
+
+// 図形の塗りと輪郭の線の属性を作る関数
+// paint は色コードかグラデーションへの参照
+fn paint_attributes(shape: &ShapeSpec, paint: &str) -> String {
+    let mut attributes = match &shape.stroke {
+        Some(stroke) if stroke.line_art => r#" fill="none""#.to_string(),
+        _ => fill(paint, shape.opacity),
+    };
+    attributes.push_str(&stroke_attributes(shape, paint));
+    attributes
+}
+
+// 図形の輪郭の線の属性を作る関数
+// 線の色がなければ paint で描く
+fn stroke_attributes(shape: &ShapeSpec, paint: &str) -> String {
+    let mut attributes = String::new();
+    if let Some(stroke) = &shape.stroke {
+        let stroke_paint = stroke.color.map_or_else(|| paint.to_string(), to_hex);
+        // ラスタ画像と同じく、線の角を丸める
+        let _ = write!(
+            attributes,
+            r#" stroke="{}" stroke-width="{}" stroke-linejoin="round""#,
+            stroke_paint, stroke.width
+        );
+        if shape.opacity < 1.0 {
+            let _ = write!(attributes, r#" stroke-opacity="{}""#, shape.opacity);
+        }
+    }
+    attributes
+}
+
+// 図形の塗りの属性を作る関数
+fn fill(paint: &str, opacity: f32) -> String {
+    if opacity < 1.0 {
+        format!(r#" fill="{}" fill-opacity="{}""#, paint, opacity)
+    } else {
+        format!(r#" fill="{}""#, paint)
+    }
+}
+
+// グラデーションをSVGの要素に変換する関数
+// 座標は viewBox の座標で指定する
+fn gradient_element(id: &str, from: Rgb<u8>, to: Rgb<u8>, ramp: &Ramp) -> String {
+    let stops = format!(
+        r#"<stop offset="0" stop-color="{}"/><stop offset="1" stop-color="{}"/>"#,
+        to_hex(from),
+        to_hex(to)
+    );
+    match *ramp {
+        Ramp::Linear { start, end } => format!(
+            r#"<linearGradient id="{}" gradientUnits="userSpaceOnUse" x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}">{}</linearGradient>"#,
+            id, start.0, start.1, end.0, end.1, stops
+        ),
+        Ramp::Radial { center, radius } => format!(
+            r#"<radialGradient id="{}" gradientUnits="userSpaceOnUse" cx="{:.1}" cy="{:.1}" r="{:.1}">{}</radialGradient>"#,
+            id, center.0, center.1, radius, stops
+        ),
+    }
+}
+
+// XML の属性値に使えない文字を実体参照に置き換える関数
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,102 @@
+use crate::{ShapeSpec, ICON_SIZE};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+// 境界線上の図形をどちらの側に含めるかを決めるための許容誤差
+const EPSILON: f32 = 1e-3;
+
+// 構図の対称性
+// 構図には対称の元になる部分の図形だけを持ち、描画するときに残りを写して描く
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Symmetry {
+    // 左半分を右半分に写す左右対称
+    Horizontal,
+    // 上半分を下半分に写す上下対称
+    Vertical,
+    // 中心の周りに 90 度ずつ回した4回対称
+    Rot4,
+    // 中心の周りに 60 度ずつ回した6回対称
+    Rot6,
+}
+
+impl Symmetry {
+    // 名前から対称性を選ぶ関数
+    pub fn from_name(name: &str) -> Option<Symmetry> {
+        match name {
+            "horizontal" => Some(Symmetry::Horizontal),
+            "vertical" => Some(Symmetry::Vertical),
+            "rot4" => Some(Symmetry::Rot4),
+            "rot6" => Some(Symmetry::Rot6),
+            _ => None,
+        }
+    }
+
+    // 図形の中心が対称の元になる部分にあるか判定する関数
+    // 対称の軸や中心の上にある図形も含める
+    pub fn contains(self, position: (f32, f32)) -> bool {
+        let center = ICON_SIZE as f32 / 2.0;
+        let (dx, dy) = (position.0 - center, position.1 - center);
+        match self {
+            Symmetry::Horizontal => dx < EPSILON,
+            Symmetry::Vertical => dy < EPSILON,
+            Symmetry::Rot4 | Symmetry::Rot6 => {
+                if dx.hypot(dy) < EPSILON {
+                    return true;
+                }
+                let theta = (dy.atan2(dx) + EPSILON).rem_euclid(2.0 * PI);
+                theta < 2.0 * PI / self.order() as f32
+            }
+        }
+    }
+
+    // 元の図形も含めた、写した図形の数
+    fn order(self) -> u32 {
+        match self {
+            Symmetry::Horizontal | Symmetry::Vertical => 2,
+            Symmetry::Rot4 => 4,
+            Symmetry::Rot6 => 6,
+        }
+    }
+
+    // 図形とそれを写した図形を並べる関数
+    // 鏡映では図形の向きも裏返るので mirrored を反転させる
+    pub fn images(self, shape: &ShapeSpec) -> Vec<ShapeSpec> {
+        let center = ICON_SIZE as f32 / 2.0;
+        let (x, y) = shape.position;
+        let mut images = vec![shape.clone()];
+        match self {
+            Symmetry::Horizontal => images.push(ShapeSpec {
+                position: (2.0 * center - x, y),
+                mirrored: !shape.mirrored,
+                ..shape.clone()
+            }),
+            // 上下の鏡映は左右の鏡映を 180 度回したもの
+            Symmetry::Vertical => images.push(ShapeSpec {
+                position: (x, 2.0 * center - y),
+                angle: shape.angle + PI,
+                mirrored: !shape.mirrored,
+                ..shape.clone()
+            }),
+            Symmetry::Rot4 | Symmetry::Rot6 => {
+                for k in 1..self.order() {
+                    let phi = 2.0 * PI * k as f32 / self.order() as f32;
+                    let (sin, cos) = phi.sin_cos();
+                    let (dx, dy) = (x - center, y - center);
+                    // 裏返した図形は回す向きも逆になる
+                    let angle = if shape.mirrored {
+                        shape.angle - phi
+                    } else {
+                        shape.angle + phi
+                    };
+                    images.push(ShapeSpec {
+                        position: (center + dx * cos - dy * sin, center + dx * sin + dy * cos),
+                        angle,
+                        ..shape.clone()
+                    });
+                }
+            }
+        }
+        images
+    }
+}
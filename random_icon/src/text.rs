@@ -0,0 +1,436 @@
+use crate::color::contrast_ratio;
+use crate::raster::blend_pixel;
+use ab_glyph::{point, Font, FontArc, Glyph, GlyphId, OutlineCurve, Point, PxScale, ScaleFont};
+use image::{Rgb, Rgba, RgbaImage};
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+// 重ねる文字列に使う組み込みのフォント (DejaVu Sans Bold)
+static OVERLAY_FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSans-Bold.ttf");
+// 重ねる文字列に使うフォント
+// 文字ごとに、先頭から順にその文字のグリフを持つフォントを選ぶ
+static OVERLAY_FONTS: OnceLock<Vec<FontArc>> = OnceLock::new();
+
+// 重ねる文字列の最大の文字数
+pub const MAX_OVERLAY_CHARS: usize = 3;
+// 重ねる文字列の大きさの上限 (アイコンの一辺に対する割合)
+const OVERLAY_MAX_WIDTH: f32 = 0.6;
+const OVERLAY_MAX_HEIGHT: f32 = 0.45;
+// 文字の色の候補
+const OVERLAY_COLORS: [Rgb<u8>; 2] = [Rgb([255, 255, 255]), Rgb([0, 0, 0])];
+
+// 組み込みのフォントにない文字 (漢字や仮名など) に使うフォントを設定する関数
+// 最初にアイコンを生成する前に呼ぶ必要があり、すでに設定されていれば渡したフォントを返す
+pub fn install_fallback_fonts(fonts: Vec<FontArc>) -> Result<(), Vec<FontArc>> {
+    let mut all = vec![overlay_font()];
+    all.extend(fonts);
+    OVERLAY_FONTS.set(all).map_err(|mut all| all.split_off(1))
+}
+
+fn overlay_font() -> FontArc {
+    FontArc::try_from_slice(OVERLAY_FONT).expect("embedded font is valid")
+}
+
+// 重ねる文字列に使うフォントを返す関数
+// install_fallback_fonts されていなければ組み込みのフォントだけを使う
+fn overlay_fonts() -> &'static [FontArc] {
+    OVERLAY_FONTS.get_or_init(|| vec![overlay_font()])
+}
+
+// 文字のグリフを持つ最初のフォントを選ぶ関数
+fn font_for(c: char) -> Option<(usize, &'static FontArc)> {
+    overlay_fonts()
+        .iter()
+        .enumerate()
+        .find(|(_, font)| font.glyph_id(c).0 != 0)
+}
+
+// ラベルの描画に使う組み込みのフォント (DejaVu Sans)
+static LABEL_FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+static LABEL_FONT_ARC: OnceLock<FontArc> = OnceLock::new();
+
+fn label_font() -> &'static FontArc {
+    LABEL_FONT_ARC
+        .get_or_init(|| FontArc::try_from_slice(LABEL_FONT).expect("embedded font is valid"))
+}
+
+// ラベルの文字に使うフォントを選ぶ関数
+// ラベルのフォントにない文字は重ねる文字列と同じフォントで描き、どれにもなければ代替のグリフを描く
+fn label_font_for(c: char) -> Option<(usize, &'static FontArc)> {
+    if label_font().glyph_id(c).0 != 0 {
+        return Some((0, label_font()));
+    }
+    match font_for(c) {
+        // 番号はカーニングを入れるかどうかの判定にだけ使うので、ラベルのフォントと重ならないようにずらす
+        Some((index, font)) => Some((index + 1, font)),
+        None => Some((0, label_font())),
+    }
+}
+
+// 文字列を指定した幅に収めたラベルの文字を求める関数
+// 収まらなければ末尾を削って … を付ける
+pub fn fit_label(text: &str, px: f32, max_width: f32) -> String {
+    let width = |text: &str| layout_line(text, px, label_font_for).1;
+    if width(text) <= max_width {
+        return text.to_string();
+    }
+    let mut chars: Vec<char> = text.chars().collect();
+    while !chars.is_empty() {
+        chars.pop();
+        let candidate: String = chars.iter().chain(['…'].iter()).collect();
+        if width(&candidate) <= max_width {
+            return candidate;
+        }
+    }
+    String::new()
+}
+
+// ラベルを水平方向の中央とベースラインの位置を指定して並べる関数
+pub fn layout_label(text: &str, px: f32, center_x: f32, baseline_y: f32) -> Vec<PlacedGlyph> {
+    let (mut glyphs, width) = layout_line(text, px, label_font_for);
+    for placed in &mut glyphs {
+        let position = placed.glyph.position;
+        placed.glyph.position = point(position.x + center_x - width / 2.0, baseline_y);
+    }
+    glyphs
+}
+
+// 文字列を1行に並べる関数
+// 位置は文字列の左端のベースラインを原点にした値で、並べた幅も返す
+// fonts で選んだフォントがない文字は飛ばす
+fn layout_line<F>(text: &str, px: f32, fonts: F) -> (Vec<PlacedGlyph>, f32)
+where
+    F: Fn(char) -> Option<(usize, &'static FontArc)>,
+{
+    let mut glyphs = Vec::new();
+    let mut x = 0.0;
+    let mut previous: Option<(usize, GlyphId)> = None;
+    for c in text.chars() {
+        let Some((index, font)) = fonts(c) else {
+            continue;
+        };
+        let scaled = font.as_scaled(PxScale::from(px));
+        let id = font.glyph_id(c);
+        // カーニングは同じフォントのグリフの間だけに入れる
+        if let Some((previous_index, previous_id)) = previous {
+            if previous_index == index {
+                x += scaled.kern(previous_id, id);
+            }
+        }
+        glyphs.push(PlacedGlyph {
+            font,
+            glyph: id.with_scale_and_position(px, point(x, 0.0)),
+        });
+        x += scaled.h_advance(id);
+        previous = Some((index, id));
+    }
+    (glyphs, x)
+}
+
+// 重ねる文字列が描けるか確かめる関数
+pub fn check_overlay(text: &str) -> Result<(), String> {
+    let count = text.chars().count();
+    if text.trim().is_empty() || count > MAX_OVERLAY_CHARS {
+        return Err(format!(
+            "text must have between 1 and {} characters: {:?}",
+            MAX_OVERLAY_CHARS, text
+        ));
+    }
+    let missing: String = text
+        .chars()
+        .filter(|&c| !c.is_whitespace() && font_for(c).is_none())
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!("no font can draw the characters {:?}", missing));
+    }
+    Ok(())
+}
+
+// 位置を決めたグリフとそれを描くフォント
+pub struct PlacedGlyph {
+    font: &'static FontArc,
+    glyph: Glyph,
+}
+
+impl PlacedGlyph {
+    // グリフの墨の部分を囲む矩形 (左, 上, 右, 下)
+    fn ink_bounds(&self) -> Option<(f32, f32, f32, f32)> {
+        let bounds = self.font.outline(self.glyph.id)?.bounds;
+        let factor = self.font.as_scaled(self.glyph.scale).scale_factor();
+        let position = self.glyph.position;
+        // ab_glyph の輪郭の矩形は、px_bounds と同じく y を反転させると上下が min, max の順になる
+        Some((
+            position.x + bounds.min.x * factor.horizontal,
+            position.y - bounds.min.y * factor.vertical,
+            position.x + bounds.max.x * factor.horizontal,
+            position.y - bounds.max.y * factor.vertical,
+        ))
+    }
+}
+
+// 文字列を size 四方のアイコンの中央に収まるように並べる関数
+// 墨の部分の中心をアイコンの中心に合わせるので、大文字でも漢字でも上下の中央に来る
+pub fn layout_overlay(text: &str, size: f32) -> Vec<PlacedGlyph> {
+    // 仮の大きさで並べてから、上限に収まるように縮める
+    let px = size;
+    let (mut glyphs, _) = layout_line(text, px, font_for);
+
+    let Some((min_x, min_y, max_x, max_y)) = glyphs
+        .iter()
+        .filter_map(PlacedGlyph::ink_bounds)
+        .reduce(|a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)))
+    else {
+        return Vec::new();
+    };
+    let scale = (size * OVERLAY_MAX_WIDTH / (max_x - min_x))
+        .min(size * OVERLAY_MAX_HEIGHT / (max_y - min_y));
+    let center = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+    for placed in &mut glyphs {
+        let position = placed.glyph.position;
+        placed.glyph.position = point(
+            (position.x - center.0) * scale + size / 2.0,
+            (position.y - center.1) * scale + size / 2.0,
+        );
+        placed.glyph.scale = PxScale::from(px * scale);
+    }
+    glyphs
+}
+
+// 並べた文字列を描画する関数
+// antialias が false なら半分以上覆われた画素だけを塗る
+pub fn draw_glyphs(img: &mut RgbaImage, glyphs: &[PlacedGlyph], color: Rgba<u8>, antialias: bool) {
+    for_each_covered_pixel(img.width(), img.height(), glyphs, |x, y, coverage| {
+        let coverage = if antialias {
+            coverage
+        } else if coverage >= 0.5 {
+            1.0
+        } else {
+            0.0
+        };
+        if coverage > 0.0 {
+            blend_pixel(img, x, y, color, coverage);
+        }
+    });
+}
+
+// 文字列が覆う画像の内側の画素ごとに、覆う割合を渡して呼び出す関数
+fn for_each_covered_pixel<F: FnMut(u32, u32, f32)>(
+    width: u32,
+    height: u32,
+    glyphs: &[PlacedGlyph],
+    mut f: F,
+) {
+    for placed in glyphs {
+        let Some(outlined) = placed.font.outline_glyph(placed.glyph.clone()) else {
+            continue;
+        };
+        let bounds = outlined.px_bounds();
+        outlined.draw(|gx, gy, coverage| {
+            let x = bounds.min.x as i64 + gx as i64;
+            let y = bounds.min.y as i64 + gy as i64;
+            if (0..width as i64).contains(&x) && (0..height as i64).contains(&y) {
+                f(x as u32, y as u32, coverage.min(1.0));
+            }
+        });
+    }
+}
+
+// 文字列の下にある画素とのコントラスト比が最も低くなる所でも、できるだけ高くなる文字の色を選ぶ関数
+// 文字の下が透明なら黒にする
+pub fn contrasting_text_color(img: &RgbaImage, glyphs: &[PlacedGlyph]) -> Rgb<u8> {
+    let mut lowest = OVERLAY_COLORS.map(|_| f32::MAX);
+    let mut sampled = false;
+    for_each_covered_pixel(img.width(), img.height(), glyphs, |x, y, coverage| {
+        let pixel = img.get_pixel(x, y);
+        if coverage < 0.5 || pixel[3] == 0 {
+            return;
+        }
+        let under = Rgb([pixel[0], pixel[1], pixel[2]]);
+        for (lowest, &color) in lowest.iter_mut().zip(OVERLAY_COLORS.iter()) {
+            *lowest = lowest.min(contrast_ratio(color, under));
+        }
+        sampled = true;
+    });
+    if !sampled {
+        return Rgb([0, 0, 0]);
+    }
+    let best = (0..OVERLAY_COLORS.len())
+        .max_by(|&a, &b| lowest[a].total_cmp(&lowest[b]))
+        .expect("there are text color candidates");
+    OVERLAY_COLORS[best]
+}
+
+// 並べた文字列の輪郭をSVGのパスデータに変換する関数
+// 輪郭は非ゼロ規則で塗る
+pub fn overlay_path_data(glyphs: &[PlacedGlyph]) -> String {
+    let mut data = String::new();
+    for placed in glyphs {
+        let Some(outline) = placed.font.outline(placed.glyph.id) else {
+            continue;
+        };
+        let factor = placed.font.as_scaled(placed.glyph.scale).scale_factor();
+        let position = placed.glyph.position;
+        let to_px = |p: &Point| {
+            format!(
+                "{:.1} {:.1}",
+                position.x + p.x * factor.horizontal,
+                position.y - p.y * factor.vertical
+            )
+        };
+        // 前の曲線の終点から続かない曲線は新しい輪郭の始まり
+        let mut last: Option<Point> = None;
+        for curve in &outline.curves {
+            let (start, end) = match curve {
+                OutlineCurve::Line(p0, p1) => (p0, p1),
+                OutlineCurve::Quad(p0, _, p2) => (p0, p2),
+                OutlineCurve::Cubic(p0, _, _, p3) => (p0, p3),
+            };
+            if last != Some(*start) {
+                if last.is_some() {
+                    data.push('Z');
+                }
+                let _ = write!(data, "M{}", to_px(start));
+            }
+            let _ = match curve {
+                OutlineCurve::Line(_, p1) => write!(data, "L{}", to_px(p1)),
+                OutlineCurve::Quad(_, p1, p2) => write!(data, "Q{} {}", to_px(p1), to_px(p2)),
+                OutlineCurve::Cubic(_, p1, p2, p3) => {
+                    write!(data, "C{} {} {}", to_px(p1), to_px(p2), to_px(p3))
+                }
+            };
+            last = Some(*end);
+        }
+        if last.is_some() {
+            data.push('Z');
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 山 (U+5C71) だけを持つ TrueType フォントを作る関数
+    // グリフは縦線3本を下でつないだ1つの輪郭で、em は 1000
+    fn cjk_test_font() -> FontArc {
+        let be16 = |v: i32| (v as u16).to_be_bytes();
+        let outline: [(i32, i32); 12] = [
+            (100, 0),
+            (100, 600),
+            (200, 600),
+            (200, 100),
+            (450, 100),
+            (450, 800),
+            (550, 800),
+            (550, 100),
+            (800, 100),
+            (800, 600),
+            (900, 600),
+            (900, 0),
+        ];
+        let mut glyph = Vec::new();
+        for v in [1, 100, 0, 900, 800, outline.len() as i32 - 1, 0] {
+            glyph.extend(be16(v));
+        }
+        glyph.extend([1; 12]);
+        for axis in [0, 1] {
+            let mut previous = 0;
+            for point in outline {
+                let v = if axis == 0 { point.0 } else { point.1 };
+                glyph.extend(be16(v - previous));
+                previous = v;
+            }
+        }
+        while glyph.len() % 4 != 0 {
+            glyph.push(0);
+        }
+
+        let table = |values: &[i32]| -> Vec<u8> { values.iter().flat_map(|&v| be16(v)).collect() };
+        let mut head = table(&[1, 0, 1, 0, 0, 0, 0x5f0f, 0x3cf5, 0, 1000]);
+        head.extend([0; 16]);
+        head.extend(table(&[100, 0, 900, 800, 0, 8, 2, 1, 0]));
+        let hhea = table(&[
+            1, 0, 800, -200, 0, 1000, 0, 0, 900, 1, 0, 0, 0, 0, 0, 0, 0, 2,
+        ]);
+        let maxp = table(&[0, 0x5000, 2]);
+        let hmtx = table(&[1000, 0, 1000, 100]);
+        let loca = table(&[0, 0, 0, 0, 0, glyph.len() as i32]);
+        let cmap = table(&[
+            0,
+            1,
+            3,
+            1,
+            0,
+            12, // 文字コード表の一覧
+            4,
+            32,
+            0,
+            4,
+            4,
+            1,
+            0, // format 4
+            0x5c71,
+            0xffff,
+            0,
+            0x5c71,
+            0xffff,
+            1 - 0x5c71,
+            1,
+            0,
+            0,
+        ]);
+
+        let tables = [
+            (b"cmap", cmap),
+            (b"glyf", glyph),
+            (b"head", head),
+            (b"hhea", hhea),
+            (b"hmtx", hmtx),
+            (b"loca", loca),
+            (b"maxp", maxp),
+        ];
+        let mut font = table(&[1, 0, tables.len() as i32, 64, 2, 48]);
+        let mut offset = 12 + 16 * tables.len();
+        let mut data = Vec::new();
+        for (tag, bytes) in &tables {
+            font.extend(*tag);
+            font.extend([0; 4]);
+            font.extend((offset as u32).to_be_bytes());
+            font.extend((bytes.len() as u32).to_be_bytes());
+            data.extend(bytes);
+            while data.len() % 4 != 0 {
+                data.push(0);
+            }
+            offset = 12 + 16 * tables.len() + data.len();
+        }
+        font.extend(data);
+        FontArc::try_from_vec(font).unwrap()
+    }
+
+    #[test]
+    fn cjk_text_lays_out_and_draws_with_a_fallback_font() {
+        assert!(overlay_font().glyph_id('山').0 == 0);
+        install_fallback_fonts(vec![cjk_test_font()]).unwrap();
+        assert!(check_overlay("山").is_ok());
+        assert!(check_overlay("山川").is_err());
+
+        let size = 100;
+        let glyphs = layout_overlay("山", size as f32);
+        assert_eq!(glyphs.len(), 1);
+        let mut img = RgbaImage::new(size, size);
+        draw_glyphs(&mut img, &glyphs, Rgba([0, 0, 0, 255]), false);
+        let ink = |x: u32, y: u32| img.get_pixel(x, y)[3] > 0;
+        // 高さの上限で 45 ピクセル四方に縮められ、中央の 27.5 から 72.5 に置かれる
+        // 縦線3本と下の横線が塗られ、縦線の間の上の方は空いている
+        assert!(ink(30, 55) && ink(50, 30) && ink(70, 55));
+        assert!(ink(40, 70) && ink(60, 70));
+        assert!(!ink(40, 45) && !ink(60, 45));
+        assert!(!ink(50, 20) && !ink(50, 80));
+
+        // ラベルでも同じフォントで描く
+        let (label, width) = layout_line("山", 20.0, label_font_for);
+        assert_eq!(label.len(), 1);
+        assert_eq!(width, 20.0);
+    }
+}